@@ -1,24 +1,141 @@
-use crate::task::Existence;
+use crate::task::{CasOutcome, Existence, Metadata, TxnOutcome, UpdateOp};
 use crate::Result;
 use std::collections::{BTreeMap, HashMap};
 use std::hash::BuildHasher;
+use std::time::Duration;
 
-pub use self::cannyls::{CannyLsDevice, CannyLsOptions, CannyLsStorage};
-pub use self::fs::FileSystemKvs;
-pub use self::rocksdb::RocksDb;
+#[cfg(feature = "cannyls")]
+pub use self::cannyls::{
+    CannyLsDevice, CannyLsKeyMode, CannyLsOptions, CannyLsStorage, CannyLsValue,
+};
+pub use self::exec::ExecKvs;
+#[cfg(feature = "fjall")]
+pub use self::fjall::Fjall;
+pub use self::fs::{
+    FileSystemHash, FileSystemKeyEncoding, FileSystemKvs, FileSystemLayout, FileSystemWriteStrategy,
+};
+#[cfg(feature = "lmdb")]
+pub use self::lmdb::{Lmdb, LmdbOptions};
+#[cfg(feature = "redb")]
+pub use self::redb::Redb;
+pub use self::resp::RespKvs;
+#[cfg(feature = "rocksdb")]
+pub use self::rocksdb::{RocksDb, RocksDbColumnFamily};
+#[cfg(feature = "rocksdb")]
+pub use self::rocksdb_txn::{RocksDbOptimistic, RocksDbTxn};
+#[cfg(feature = "sled")]
 pub use self::sled::SledTree;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::{Sqlite, SqliteJournalMode, SqliteOptions, SqliteSynchronous};
 
+pub mod exec;
+pub mod resp;
+pub mod testing;
+
+#[cfg(feature = "cannyls")]
 mod cannyls;
+#[cfg(feature = "fjall")]
+mod fjall;
 mod fs;
+#[cfg(feature = "lmdb")]
+mod lmdb;
+#[cfg(feature = "redb")]
+mod redb;
+#[cfg(feature = "rocksdb")]
 mod rocksdb;
+#[cfg(feature = "rocksdb")]
+mod rocksdb_txn;
+#[cfg(feature = "sled")]
 mod sled;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
 pub trait KeyValueStore {
-    type OwnedValue;
+    type OwnedValue: AsRef<[u8]>;
 
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence>;
     fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>>;
     fn delete(&mut self, key: &[u8]) -> Result<Existence>;
+
+    /// Reads the values of `keys` in a single call.
+    ///
+    /// Stores without batched reads emulate it by calling `get` for each key.
+    fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Self::OwnedValue>>> {
+        keys.iter().map(|key| track!(self.get(key))).collect()
+    }
+
+    /// Reads the value of `key`, applies `op` to it and writes the result.
+    ///
+    /// Stores without native support emulate it by a plain read and write.
+    fn update(&mut self, key: &[u8], op: &UpdateOp) -> Result<Existence> {
+        let old = track!(self.get(key))?;
+        let new = op.apply(old.as_ref().map(AsRef::as_ref));
+        track!(self.put(key, &new))?;
+        Ok(Existence::new(old.is_some()))
+    }
+
+    /// Reads all `keys` and then writes `value` to each of them in a single transaction.
+    ///
+    /// Stores without transactions emulate it by plain (non-atomic) reads and writes.
+    fn transaction(&mut self, keys: &[&[u8]], value: &[u8]) -> Result<TxnOutcome> {
+        for key in keys {
+            track!(self.get(key))?;
+        }
+        for key in keys {
+            track!(self.put(key, value))?;
+        }
+        Ok(TxnOutcome::Committed { retries: 0 })
+    }
+
+    /// Replaces the value of `key` with `value` only if the current value equals `expected`
+    /// (`None` means that `key` does not exist).
+    ///
+    /// Stores without compare-and-swap emulate it by a plain (non-atomic) read and write.
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<CasOutcome> {
+        let current = track!(self.get(key))?;
+        if current.as_ref().map(AsRef::as_ref) != expected {
+            return Ok(CasOutcome::Failed);
+        }
+        track!(self.put(key, value))?;
+        Ok(CasOutcome::Swapped)
+    }
+
+    /// Makes the results of all preceding writes durable.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the time spent on probing key existence since the last call.
+    ///
+    /// The time is excluded from the latency of the operation and reported separately.
+    fn take_probe_elapsed(&mut self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns the keys whose earlier successful writes have been rolled back since the last call
+    /// (e.g., because a later operation of the same group commit failed).
+    ///
+    /// A model check re-reads them to follow the actual state of the store.
+    fn take_discarded_keys(&mut self) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+
+    /// Does periodic housekeeping (e.g., statistics collection) after each task.
+    ///
+    /// `WorkloadExecutor` calls this outside of the measured latency of the task.
+    fn after_task(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns backend-specific information to be attached to the run result.
+    fn metadata(&mut self) -> Result<Metadata> {
+        Ok(Metadata::new())
+    }
 }
 
 impl<S: BuildHasher> KeyValueStore for HashMap<Vec<u8>, Vec<u8>, S> {
@@ -8,6 +8,9 @@ use cannyls::lump::{LumpData, LumpId};
 use cannyls::nvm::FileNvm;
 use cannyls::storage::{Storage, StorageBuilder};
 use futures::{Async, Future};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use std::borrow::Cow;
+use std::hash::Hasher;
 use std::path::Path;
 use std::thread;
 use std::time::Duration;
@@ -17,19 +20,47 @@ use trackable::error::{ErrorKindExt, Failed, Failure};
 pub struct CannyLsOptions {
     pub capacity: u64,
     pub journal_sync_interval: usize,
+    pub key_mode: CannyLsKeyMode,
 }
 impl Default for CannyLsOptions {
     fn default() -> Self {
         CannyLsOptions {
             capacity: 1024 * 1024 * 1024,
             journal_sync_interval: 4096,
+            key_mode: CannyLsKeyMode::Direct,
         }
     }
 }
 
+/// How keys are mapped to `LumpId`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CannyLsKeyMode {
+    /// Interprets a key as a big-endian integer (keys longer than 16 bytes are rejected).
+    Direct,
+
+    /// Uses the 128-bit SipHash of a key.
+    Hash,
+
+    /// Same as `Hash`, but also stores the original key in each lump to detect collisions.
+    CheckedHash,
+}
+
+/// A value read from CannyLS.
+#[derive(Debug)]
+pub struct CannyLsValue {
+    data: LumpData,
+    offset: usize,
+}
+impl AsRef<[u8]> for CannyLsValue {
+    fn as_ref(&self) -> &[u8] {
+        &self.data.as_bytes()[self.offset..]
+    }
+}
+
 #[derive(Debug)]
 pub struct CannyLsDevice {
     device: Device,
+    key_mode: CannyLsKeyMode,
 }
 impl CannyLsDevice {
     pub fn new<P: AsRef<Path>>(lusf_file: P, options: &CannyLsOptions) -> Result<Self> {
@@ -46,34 +77,49 @@ impl CannyLsDevice {
 
         let device = DeviceBuilder::new().spawn(|| Ok(storage));
         let device = track!(wait(device.wait_for_running()))?;
-        Ok(CannyLsDevice { device })
+        Ok(CannyLsDevice {
+            device,
+            key_mode: options.key_mode,
+        })
     }
 }
 impl KeyValueStore for CannyLsDevice {
-    type OwnedValue = LumpData;
+    type OwnedValue = CannyLsValue;
 
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
-        let id = track!(bytes_to_lump_id(key))?;
+        let key = track!(LumpKey::new(key, self.key_mode))?;
+        if key.is_checked() {
+            let data = track!(wait(self.device.handle().request().get(key.id)))?;
+            track!(key.decode(data))?;
+        }
         let data = track!(self
             .device
             .handle()
-            .allocate_lump_data_with_bytes(value)
+            .allocate_lump_data_with_bytes(&key.encode(value))
             .map_err(into_failure))?;
-        let new = track!(wait(self.device.handle().request().put(id, data)))?;
+        let new = track!(wait(self.device.handle().request().put(key.id, data)))?;
         Ok(Existence::new(!new))
     }
 
     fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
-        let id = track!(bytes_to_lump_id(key))?;
-        let data = track!(wait(self.device.handle().request().get(id)))?;
-        Ok(data)
+        let key = track!(LumpKey::new(key, self.key_mode))?;
+        let data = track!(wait(self.device.handle().request().get(key.id)))?;
+        track!(key.decode(data))
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<Existence> {
-        let id = track!(bytes_to_lump_id(key))?;
-        let exists = track!(wait(self.device.handle().request().delete(id)))?;
+        let key = track!(LumpKey::new(key, self.key_mode))?;
+        if key.is_checked() {
+            let data = track!(wait(self.device.handle().request().get(key.id)))?;
+            track!(key.decode(data))?;
+        }
+        let exists = track!(wait(self.device.handle().request().delete(key.id)))?;
         Ok(Existence::new(exists))
     }
+
+    fn sync(&mut self) -> Result<()> {
+        track!(wait(self.device.handle().request().journal_sync()))
+    }
 }
 impl Drop for CannyLsDevice {
     fn drop(&mut self) {
@@ -97,6 +143,7 @@ where
 #[derive(Debug)]
 pub struct CannyLsStorage {
     storage: Storage<FileNvm>,
+    key_mode: CannyLsKeyMode,
 }
 impl CannyLsStorage {
     pub fn new<P: AsRef<Path>>(lusf_file: P, options: &CannyLsOptions) -> Result<Self> {
@@ -110,33 +157,111 @@ impl CannyLsStorage {
         } else {
             track!(storage.open(nvm).map_err(into_failure))?
         };
-        Ok(CannyLsStorage { storage })
+        Ok(CannyLsStorage {
+            storage,
+            key_mode: options.key_mode,
+        })
     }
 }
 impl KeyValueStore for CannyLsStorage {
-    type OwnedValue = LumpData;
+    type OwnedValue = CannyLsValue;
 
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
-        let id = track!(bytes_to_lump_id(key))?;
+        let key = track!(LumpKey::new(key, self.key_mode))?;
+        if key.is_checked() {
+            let data = track!(self.storage.get(&key.id).map_err(into_failure))?;
+            track!(key.decode(data))?;
+        }
         let data = track!(self
             .storage
-            .allocate_lump_data_with_bytes(value)
+            .allocate_lump_data_with_bytes(&key.encode(value))
             .map_err(into_failure))?;
-        let new = track!(self.storage.put(&id, &data).map_err(into_failure))?;
+        let new = track!(self.storage.put(&key.id, &data).map_err(into_failure))?;
         Ok(Existence::new(!new))
     }
 
     fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
-        let id = track!(bytes_to_lump_id(key))?;
-        let data = track!(self.storage.get(&id).map_err(into_failure))?;
-        Ok(data)
+        let key = track!(LumpKey::new(key, self.key_mode))?;
+        let data = track!(self.storage.get(&key.id).map_err(into_failure))?;
+        track!(key.decode(data))
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<Existence> {
-        let id = track!(bytes_to_lump_id(key))?;
-        let exists = track!(self.storage.delete(&id).map_err(into_failure))?;
+        let key = track!(LumpKey::new(key, self.key_mode))?;
+        if key.is_checked() {
+            let data = track!(self.storage.get(&key.id).map_err(into_failure))?;
+            track!(key.decode(data))?;
+        }
+        let exists = track!(self.storage.delete(&key.id).map_err(into_failure))?;
         Ok(Existence::new(exists))
     }
+
+    fn sync(&mut self) -> Result<()> {
+        track!(self.storage.journal_sync().map_err(into_failure))
+    }
+}
+
+/// A key mapped to a `LumpId`.
+///
+/// In `CheckedHash` mode, each lump starts with the length of the original key
+/// (4 bytes, big-endian) followed by the key itself.
+struct LumpKey<'a> {
+    id: LumpId,
+    checked_key: Option<&'a [u8]>,
+}
+impl<'a> LumpKey<'a> {
+    fn new(key: &'a [u8], mode: CannyLsKeyMode) -> Result<Self> {
+        let (id, checked_key) = match mode {
+            CannyLsKeyMode::Direct => (track!(bytes_to_lump_id(key))?, None),
+            CannyLsKeyMode::Hash => (hash_to_lump_id(key), None),
+            CannyLsKeyMode::CheckedHash => (hash_to_lump_id(key), Some(key)),
+        };
+        Ok(LumpKey { id, checked_key })
+    }
+
+    fn is_checked(&self) -> bool {
+        self.checked_key.is_some()
+    }
+
+    fn encode<'b>(&self, value: &'b [u8]) -> Cow<'b, [u8]> {
+        if let Some(key) = self.checked_key {
+            let mut buf = Vec::with_capacity(4 + key.len() + value.len());
+            buf.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(value);
+            Cow::Owned(buf)
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    fn decode(&self, data: Option<LumpData>) -> Result<Option<CannyLsValue>> {
+        let data = if let Some(data) = data {
+            data
+        } else {
+            return Ok(None);
+        };
+        let offset = if let Some(key) = self.checked_key {
+            let bytes = data.as_bytes();
+            track_assert!(bytes.len() >= 4, Failed; self.id, bytes.len());
+            let mut len = [0; 4];
+            len.copy_from_slice(&bytes[..4]);
+            let end = 4 + u32::from_be_bytes(len) as usize;
+            track_assert!(bytes.len() >= end, Failed; self.id, bytes.len(), end);
+            track_assert!(
+                &bytes[4..end] == key,
+                Failed,
+                "LumpId collision: id={:?}, key={:?}, stored_key={:?}",
+                self.id,
+                key,
+                &bytes[4..end]
+            );
+            end
+        } else {
+            0
+        };
+        Ok(Some(CannyLsValue { data, offset }))
+    }
 }
 
 fn bytes_to_lump_id(bytes: &[u8]) -> Result<LumpId> {
@@ -148,6 +273,12 @@ fn bytes_to_lump_id(bytes: &[u8]) -> Result<LumpId> {
     Ok(LumpId::new(id))
 }
 
+fn hash_to_lump_id(bytes: &[u8]) -> LumpId {
+    let mut hasher = SipHasher13::new();
+    hasher.write(bytes);
+    LumpId::new(hasher.finish128().as_u128())
+}
+
 fn into_failure(e: cannyls::Error) -> Failure {
     Failed.takes_over(e).into()
 }
@@ -0,0 +1,362 @@
+//! A backend driving an external program over a length-prefixed protocol.
+//!
+//! `ExecKvs` writes requests to the stdin of a child process and reads the responses
+//! from its stdout, one at a time. Integers are big-endian, and a byte string is
+//! a `u32` length followed by the bytes.
+//!
+//! A request is a `u32` length of the rest of the request, an operation code and its fields.
+//! The length lets a server skip the fields of an operation it does not know:
+//!
+//! - `P` (put): key and value
+//! - `G` (get): key
+//! - `D` (delete): key
+//! - `M` (multi-get): `u32` number of keys and the keys
+//! - `S` (sync): nothing
+//!
+//! Responses start with a status:
+//!
+//! - `0` (ok): followed by the operation specific payload below
+//! - `1` (error): followed by a UTF-8 error message (a byte string)
+//! - `2` (unsupported): nothing; multi-get then falls back to gets and sync becomes a no-op
+//!
+//! A server should reply `2` to an unknown operation and `1` to a malformed request.
+//! A malformed or truncated response breaks the connection, and later requests fail.
+//!
+//! The payload of put and delete is an existence byte (`0`: absent, `1`: present,
+//! `2`: unknown), that of get is a presence byte (`0` or `1`) followed by the value if present,
+//! and that of multi-get is a get payload per key. Sync has no payload.
+//!
+//! The child process should exit when its stdin is closed.
+//! `serve` implements the protocol on top of any `KeyValueStore`.
+use crate::kvs::KeyValueStore;
+use crate::task::Existence;
+use crate::Result;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use trackable::error::{ErrorKindExt, Failed};
+
+const OP_PUT: u8 = b'P';
+const OP_GET: u8 = b'G';
+const OP_DELETE: u8 = b'D';
+const OP_MULTI_GET: u8 = b'M';
+const OP_SYNC: u8 = b'S';
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+const STATUS_UNSUPPORTED: u8 = 2;
+
+const ABSENT: u8 = 0;
+const PRESENT: u8 = 1;
+const UNKNOWN: u8 = 2;
+
+pub struct ExecKvs {
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    stdout: BufReader<ChildStdout>,
+    multi_get_supported: bool,
+
+    // Set while a request is in flight, and left set if it fails before its response has
+    // been read completely (the stream is no longer aligned to responses then).
+    broken: bool,
+}
+impl ExecKvs {
+    /// Spawns `command` with piped stdin and stdout (stderr is inherited).
+    pub fn new(mut command: Command) -> Result<Self> {
+        let mut child =
+            track_any_err!(command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn())?;
+        let stdin = track_assert_some!(child.stdin.take(), Failed);
+        let stdout = track_assert_some!(child.stdout.take(), Failed);
+        Ok(ExecKvs {
+            child,
+            stdin: Some(BufWriter::new(stdin)),
+            stdout: BufReader::new(stdout),
+            multi_get_supported: true,
+            broken: false,
+        })
+    }
+
+    /// Exchanges a request and its response by `f`.
+    fn exchange<F, T>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        track_assert!(!self.broken, Failed, "Broken by an earlier error");
+        self.broken = true;
+        let result = f(self);
+        if result.is_ok() {
+            self.broken = false;
+        }
+        result
+    }
+
+    /// Sends a request consisting of `op`, `header` (as is) and `fields` (as byte strings).
+    fn send(&mut self, op: u8, header: &[u8], fields: &[&[u8]]) -> Result<()> {
+        let len = fields
+            .iter()
+            .fold(1 + header.len(), |len, field| len + 4 + field.len());
+        let stdin = track_assert_some!(self.stdin.as_mut(), Failed);
+        track!(write_u32(stdin, len))?;
+        track_any_err!(stdin.write_all(&[op]))?;
+        track_any_err!(stdin.write_all(header))?;
+        for field in fields {
+            track!(write_bytes(stdin, field))?;
+        }
+        track_any_err!(stdin.flush())?;
+        Ok(())
+    }
+
+    /// Returns `false` if the child process does not support the request.
+    fn recv_status(&mut self) -> Result<bool> {
+        match track!(read_u8(&mut self.stdout))? {
+            STATUS_OK => Ok(true),
+            STATUS_UNSUPPORTED => {
+                self.broken = false;
+                Ok(false)
+            }
+            STATUS_ERROR => {
+                let message = track!(read_bytes(&mut self.stdout))?;
+                self.broken = false;
+                track_panic!(Failed, "{}", String::from_utf8_lossy(&message))
+            }
+            status => track_panic!(Failed, "Unknown response status: {}", status),
+        }
+    }
+
+    fn call(&mut self, op: u8, fields: &[&[u8]]) -> Result<()> {
+        track!(self.send(op, &[], fields))?;
+        let supported = track!(self.recv_status())?;
+        track_assert!(supported, Failed, "Unsupported operation: {:?}", op as char);
+        Ok(())
+    }
+
+    fn recv_existence(&mut self) -> Result<Existence> {
+        match track!(read_u8(&mut self.stdout))? {
+            ABSENT => Ok(Existence::new(false)),
+            PRESENT => Ok(Existence::new(true)),
+            UNKNOWN => Ok(Existence::unknown()),
+            b => track_panic!(Failed, "Unknown existence: {}", b),
+        }
+    }
+
+    fn recv_value(&mut self) -> Result<Option<Vec<u8>>> {
+        match track!(read_u8(&mut self.stdout))? {
+            ABSENT => Ok(None),
+            PRESENT => Ok(Some(track!(read_bytes(&mut self.stdout))?)),
+            b => track_panic!(Failed, "Unknown presence: {}", b),
+        }
+    }
+}
+impl fmt::Debug for ExecKvs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExecKvs")
+            .field("child", &self.child.id())
+            .field("multi_get_supported", &self.multi_get_supported)
+            .field("broken", &self.broken)
+            .finish()
+    }
+}
+impl Drop for ExecKvs {
+    fn drop(&mut self) {
+        // Closes the stdin of the child process and waits for it to exit.
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+impl KeyValueStore for ExecKvs {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        track!(self.exchange(|this| {
+            track!(this.call(OP_PUT, &[key, value]))?;
+            track!(this.recv_existence())
+        }))
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        track!(self.exchange(|this| {
+            track!(this.call(OP_GET, &[key]))?;
+            track!(this.recv_value())
+        }))
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        track!(self.exchange(|this| {
+            track!(this.call(OP_DELETE, &[key]))?;
+            track!(this.recv_existence())
+        }))
+    }
+
+    fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Self::OwnedValue>>> {
+        if self.multi_get_supported {
+            let count = track_any_err!(u32::try_from(keys.len()))?.to_be_bytes();
+            let values = track!(self.exchange(|this| {
+                track!(this.send(OP_MULTI_GET, &count, keys))?;
+                if !track!(this.recv_status())? {
+                    return Ok(None);
+                }
+                let values = keys.iter().map(|_| track!(this.recv_value()));
+                track!(values.collect::<Result<Vec<_>>>()).map(Some)
+            }))?;
+            if let Some(values) = values {
+                return Ok(values);
+            }
+            self.multi_get_supported = false;
+        }
+        keys.iter().map(|key| track!(self.get(key))).collect()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        track!(self.exchange(|this| {
+            track!(this.send(OP_SYNC, &[], &[]))?;
+            track!(this.recv_status())?;
+            Ok(())
+        }))
+    }
+}
+
+/// Serves `kvs` over the protocol until `reader` reaches EOF.
+pub fn serve<T, R, W>(kvs: &mut T, reader: R, writer: W) -> Result<()>
+where
+    T: KeyValueStore,
+    R: Read,
+    W: Write,
+{
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    loop {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            result => track_any_err!(result)?,
+        }
+        let request = track!(read_vec(&mut reader, u32::from_be_bytes(len)))?;
+        match handle_request(kvs, &request) {
+            None => track_any_err!(writer.write_all(&[STATUS_UNSUPPORTED]))?,
+            Some(Ok(payload)) => {
+                track_any_err!(writer.write_all(&[STATUS_OK]))?;
+                track_any_err!(writer.write_all(&payload))?;
+            }
+            Some(Err(e)) => {
+                track_any_err!(writer.write_all(&[STATUS_ERROR]))?;
+                track!(write_bytes(&mut writer, e.to_string().as_bytes()))?;
+            }
+        }
+        track_any_err!(writer.flush())?;
+    }
+}
+
+/// Returns the response payload of `request`, or `None` if its operation is unknown.
+fn handle_request<T: KeyValueStore>(kvs: &mut T, mut request: &[u8]) -> Option<Result<Vec<u8>>> {
+    let op = match request.split_first() {
+        Some((op, fields)) => {
+            request = fields;
+            *op
+        }
+        None => return Some(Err(Failed.cause("Empty request"))),
+    };
+    let result = match op {
+        OP_PUT => read_bytes(&mut request).and_then(|key| {
+            let value = track!(read_bytes(&mut request))?;
+            track!(check_end(request))?;
+            let exists = track!(kvs.put(&key, &value))?;
+            Ok(vec![existence_to_u8(exists)])
+        }),
+        OP_GET => read_bytes(&mut request).and_then(|key| {
+            track!(check_end(request))?;
+            let value = track!(kvs.get(&key))?;
+            Ok(encode_value(value.as_ref()))
+        }),
+        OP_DELETE => read_bytes(&mut request).and_then(|key| {
+            track!(check_end(request))?;
+            let exists = track!(kvs.delete(&key))?;
+            Ok(vec![existence_to_u8(exists)])
+        }),
+        OP_MULTI_GET => read_u32(&mut request).and_then(|count| {
+            let keys = track!((0..count)
+                .map(|_| read_bytes(&mut request))
+                .collect::<Result<Vec<_>>>())?;
+            track!(check_end(request))?;
+            let keys = keys.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let values = track!(kvs.multi_get(&keys))?;
+            Ok(values
+                .iter()
+                .flat_map(|value| encode_value(value.as_ref()))
+                .collect())
+        }),
+        OP_SYNC => check_end(request).and_then(|()| track!(kvs.sync()).map(|()| Vec::new())),
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn check_end(request: &[u8]) -> Result<()> {
+    track_assert!(
+        request.is_empty(),
+        Failed,
+        "Malformed request: {} trailing bytes",
+        request.len()
+    );
+    Ok(())
+}
+
+fn existence_to_u8(exists: Existence) -> u8 {
+    match exists.exists() {
+        Some(false) => ABSENT,
+        Some(true) => PRESENT,
+        None => UNKNOWN,
+    }
+}
+
+fn encode_value<V: AsRef<[u8]>>(value: Option<&V>) -> Vec<u8> {
+    match value {
+        None => vec![ABSENT],
+        Some(value) => {
+            let value = value.as_ref();
+            let mut buf = Vec::with_capacity(5 + value.len());
+            buf.push(PRESENT);
+            buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            buf.extend_from_slice(value);
+            buf
+        }
+    }
+}
+
+fn write_u32<W: Write>(writer: &mut W, n: usize) -> Result<()> {
+    track_assert!(n <= u32::MAX as usize, Failed; n);
+    track_any_err!(writer.write_all(&(n as u32).to_be_bytes()))?;
+    Ok(())
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    track!(write_u32(writer, bytes.len()))?;
+    track_any_err!(writer.write_all(bytes))?;
+    Ok(())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut buf = [0];
+    track_any_err!(reader.read_exact(&mut buf))?;
+    Ok(buf[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    track_any_err!(reader.read_exact(&mut buf))?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = track!(read_u32(reader))?;
+    track!(read_vec(reader, len))
+}
+
+/// Reads `len` bytes, growing the buffer only as the bytes arrive
+/// (a malformed length must not allocate up to 4 GiB at once).
+fn read_vec<R: Read>(reader: &mut R, len: u32) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    track_any_err!(reader.take(u64::from(len)).read_to_end(&mut buf))?;
+    track_assert_eq!(buf.len(), len as usize, Failed, "Unexpected EOF");
+    Ok(buf)
+}
@@ -0,0 +1,78 @@
+use crate::kvs::KeyValueStore;
+use crate::task::Existence;
+use crate::Result;
+use fjall::{Config, Keyspace, PartitionCreateOptions, PartitionHandle, PersistMode, Slice};
+use std::fmt;
+use std::path::Path;
+
+const PARTITION: &str = "ekvsb";
+
+/// fjall store using a single partition of a keyspace.
+pub struct Fjall {
+    keyspace: Keyspace,
+    partition: PartitionHandle,
+    persist_mode: Option<PersistMode>,
+}
+impl Fjall {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        track!(Self::with_config(
+            Config::new(dir),
+            PartitionCreateOptions::default()
+        ))
+    }
+
+    pub fn with_config(config: Config, partition_options: PartitionCreateOptions) -> Result<Self> {
+        let keyspace = track_any_err!(config.open())?;
+        let partition = track_any_err!(keyspace.open_partition(PARTITION, partition_options))?;
+        Ok(Fjall {
+            keyspace,
+            partition,
+            persist_mode: None,
+        })
+    }
+
+    /// Makes every `put` and `delete` persist the journal with `mode`
+    /// (by default it is left to the journal buffering and fsync settings of the keyspace).
+    pub fn set_persist_mode(&mut self, mode: Option<PersistMode>) {
+        self.persist_mode = mode;
+    }
+
+    fn persist_if_needed(&self) -> Result<()> {
+        if let Some(mode) = self.persist_mode {
+            track_any_err!(self.keyspace.persist(mode))?;
+        }
+        Ok(())
+    }
+}
+impl fmt::Debug for Fjall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Fjall")
+            .field("persist_mode", &self.persist_mode)
+            .finish()
+    }
+}
+impl KeyValueStore for Fjall {
+    type OwnedValue = Slice;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        track_any_err!(self.partition.insert(key, value))?;
+        track!(self.persist_if_needed())?;
+        Ok(Existence::unknown())
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        let value = track_any_err!(self.partition.get(key))?;
+        Ok(value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        track_any_err!(self.partition.remove(key))?;
+        track!(self.persist_if_needed())?;
+        Ok(Existence::unknown())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        track_any_err!(self.keyspace.persist(PersistMode::SyncAll))?;
+        Ok(())
+    }
+}
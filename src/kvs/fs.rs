@@ -1,42 +1,275 @@
 use crate::kvs::KeyValueStore;
 use crate::task::Existence;
 use crate::Result;
-use percent_encoding::{percent_encode, DEFAULT_ENCODE_SET};
-use siphasher::sip::SipHasher13;
+use percent_encoding::{define_encode_set, percent_encode, DEFAULT_ENCODE_SET};
+use siphasher::sip::{SipHasher13, SipHasher24};
+use std::collections::HashSet;
 use std::fs::{self, File, OpenOptions};
 use std::hash::{Hash, Hasher};
 use std::io::{ErrorKind, Read, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use trackable::error::Failed;
 
+const TMP_DIR: &str = ".tmp";
+
+/// The maximum length of a file name component before a `#` marker or an escaped leading dot.
+const MAX_CHUNK_LEN: usize = 252;
+
+define_encode_set! {
+    /// Also encodes `%`, so that the escapes added by `FileSystemKvs` never collide with keys,
+    /// and `/`, so that a key never needs another key's file to be a directory.
+    pub FILE_NAME_ENCODE_SET = [DEFAULT_ENCODE_SET] | {'%', '/'}
+}
+
+/// How `FileSystemKvs::put` writes values to files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemWriteStrategy {
+    /// Truncates the target file and writes to it (a crash may leave a torn value).
+    InPlace,
+
+    /// Writes to a temporary file and renames it to the target file.
+    Rename,
+
+    /// Same as `Rename`, but also fsyncs the file before renaming and its parent directory after.
+    DurableRename,
+}
+
+/// Directory layout of `FileSystemKvs`.
+///
+/// A file is placed under `depth` levels of directories, each of which is one of
+/// `fan_out` shards chosen by the hash of the encoded key.
+#[derive(Debug, Clone)]
+pub struct FileSystemLayout {
+    /// The number of directory levels (`0` means a flat layout).
+    pub depth: usize,
+    pub fan_out: u64,
+    pub hash: FileSystemHash,
+    pub key_encoding: FileSystemKeyEncoding,
+}
+impl Default for FileSystemLayout {
+    fn default() -> Self {
+        FileSystemLayout {
+            depth: 3,
+            fan_out: 256,
+            hash: FileSystemHash::Sip13,
+            key_encoding: FileSystemKeyEncoding::Percent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemHash {
+    Sip13,
+    Sip24,
+    Fnv1a,
+}
+
+/// How keys are encoded into file names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemKeyEncoding {
+    /// Percent-encoding (`/` is also encoded).
+    Percent,
+
+    /// Lower-case hexadecimal.
+    Hex,
+}
+
 #[derive(Debug)]
 pub struct FileSystemKvs {
     root_dir: PathBuf,
+    layout: FileSystemLayout,
+    cleanup_empty_dirs: bool,
+    write_strategy: FileSystemWriteStrategy,
+    next_tmp_id: u64,
+    probe_existence: bool,
+    probe_elapsed: Duration,
+    track_unsynced: bool,
+    unsynced: HashSet<PathBuf>,
+    unsynced_dirs: HashSet<PathBuf>,
 }
 impl FileSystemKvs {
     pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
-        track_any_err!(fs::create_dir_all(&root_dir))?;
+        track_any_err!(fs::create_dir_all(root_dir.as_ref().join(TMP_DIR)))?;
         Ok(FileSystemKvs {
             root_dir: root_dir.as_ref().to_path_buf(),
+            layout: FileSystemLayout::default(),
+            cleanup_empty_dirs: false,
+            write_strategy: FileSystemWriteStrategy::InPlace,
+            next_tmp_id: 0,
+            probe_existence: false,
+            probe_elapsed: Duration::default(),
+            track_unsynced: true,
+            unsynced: HashSet::new(),
+            unsynced_dirs: HashSet::new(),
         })
     }
 
+    pub fn set_layout(&mut self, layout: FileSystemLayout) -> Result<()> {
+        track_assert!(layout.fan_out > 0, Failed; layout);
+        self.layout = layout;
+        Ok(())
+    }
+
+    /// Makes `delete` remove the directories that become empty.
+    pub fn enable_empty_dir_cleanup(&mut self) {
+        self.cleanup_empty_dirs = true;
+    }
+
+    pub fn set_write_strategy(&mut self, strategy: FileSystemWriteStrategy) {
+        self.write_strategy = strategy;
+    }
+
+    /// Makes `put` report accurate existence (`delete` always does).
+    ///
+    /// With `FileSystemWriteStrategy::InPlace`, `put` first tries to create a new file and
+    /// falls back to overwriting the existing one; the failed attempt is accounted as probe time.
+    /// With the other strategies, `put` looks up the metadata of the target file beforehand.
+    pub fn enable_existence_probe(&mut self) {
+        self.probe_existence = true;
+    }
+
+    /// Stops remembering the written files and directories, which makes `sync` a no-op.
+    ///
+    /// This is for runs that never sync, where the sets of the paths would only grow.
+    pub fn disable_sync(&mut self) {
+        self.track_unsynced = false;
+        self.unsynced.clear();
+        self.unsynced_dirs.clear();
+    }
+
+    fn mark_unsynced(&mut self, path: PathBuf) {
+        if self.track_unsynced {
+            self.unsynced.insert(path);
+        }
+    }
+
+    /// Remembers a directory whose entries have changed.
+    fn mark_unsynced_dir(&mut self, dir: PathBuf) {
+        if self.track_unsynced {
+            self.unsynced_dirs.insert(dir);
+        }
+    }
+
+    /// Maps `key` to a file path under the root directory.
+    ///
+    /// No path component starts with `.` (so neither `.` and `..` nor `TMP_DIR` can appear)
+    /// or is empty, and a name longer than `MAX_CHUNK_LEN` is split into directories
+    /// whose names end with `#` (which the encodings never produce).
     fn key_to_path(&self, key: &[u8]) -> PathBuf {
-        let name = percent_encode(key, DEFAULT_ENCODE_SET).to_string();
+        let mut name = match self.layout.key_encoding {
+            FileSystemKeyEncoding::Percent => percent_encode(key, FILE_NAME_ENCODE_SET).collect(),
+            FileSystemKeyEncoding::Hex => key.iter().map(|b| format!("{:02x}", b)).collect(),
+        };
+        if name.is_empty() {
+            // A lone `%` is never produced by the encodings.
+            name.push('%');
+        }
+
+        let mut path = self.root_dir.clone();
+        if self.layout.depth > 0 {
+            let mut h = match self.layout.hash {
+                FileSystemHash::Sip13 => hash(&name, SipHasher13::new()),
+                FileSystemHash::Sip24 => hash(&name, SipHasher24::new()),
+                FileSystemHash::Fnv1a => hash(&name, Fnv1aHasher::default()),
+            };
+            let fan_out = self.layout.fan_out;
+            let width = format!("{:x}", fan_out - 1).len();
+            for _ in 0..self.layout.depth {
+                path.push(format!("{:0width$x}", h % fan_out, width = width));
+                h /= fan_out;
+            }
+        }
+        // The encoded names are ASCII, so they can be split at any byte.
+        let mut rest = name.as_str();
+        while rest.len() > MAX_CHUNK_LEN {
+            let (chunk, tail) = rest.split_at(MAX_CHUNK_LEN);
+            path.push(escape_leading_dot(chunk) + "#");
+            rest = tail;
+        }
+        path.push(escape_leading_dot(rest));
+        path
+    }
+
+    fn remove_empty_dirs(&mut self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.root_dir || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+            if let Some(parent) = dir {
+                self.mark_unsynced_dir(parent.to_path_buf());
+            }
+        }
+    }
 
-        let mut hasher = SipHasher13::new();
-        name.hash(&mut hasher);
+    fn write_in_place(&mut self, path: PathBuf, value: &[u8]) -> Result<Existence> {
+        let mut exists = Existence::unknown();
+        if self.probe_existence {
+            let start = Instant::now();
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    track_any_err!(file.write_all(value))?;
+                    self.mark_unsynced(path);
+                    return Ok(Existence::new(false));
+                }
+                Err(e) => {
+                    if e.kind() != ErrorKind::AlreadyExists {
+                        track_any_err!(Err(e))?;
+                    }
+                    self.probe_elapsed += start.elapsed();
+                    exists = Existence::new(true);
+                }
+            }
+        }
+        let mut file = track_any_err!(OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path))?;
+        track_any_err!(file.write_all(value))?;
+        self.mark_unsynced(path);
+        Ok(exists)
+    }
 
-        let h = hasher.finish();
-        let file = format!(
-            "{:02x}/{:02x}/{:02x}/{}",
-            h as u8,
-            (h >> 8) as u8,
-            (h >> 16) as u8,
-            name
-        );
+    fn write_via_rename(
+        &mut self,
+        path: PathBuf,
+        value: &[u8],
+        durable: bool,
+    ) -> Result<Existence> {
+        let mut exists = Existence::unknown();
+        if self.probe_existence {
+            let start = Instant::now();
+            match fs::metadata(&path) {
+                Ok(_) => exists = Existence::new(true),
+                Err(e) => {
+                    if e.kind() != ErrorKind::NotFound {
+                        track_any_err!(Err(e))?;
+                    }
+                    exists = Existence::new(false);
+                }
+            }
+            self.probe_elapsed += start.elapsed();
+        }
 
-        self.root_dir.join(file)
+        let tmp_path = self
+            .root_dir
+            .join(TMP_DIR)
+            .join(self.next_tmp_id.to_string());
+        self.next_tmp_id += 1;
+        let mut file = track_any_err!(File::create(&tmp_path))?;
+        track_any_err!(file.write_all(value))?;
+        if durable {
+            track_any_err!(file.sync_all())?;
+        }
+        track_any_err!(fs::rename(&tmp_path, &path))?;
+        if !durable {
+            self.mark_unsynced(path);
+        }
+        Ok(exists)
     }
 }
 impl KeyValueStore for FileSystemKvs {
@@ -44,17 +277,30 @@ impl KeyValueStore for FileSystemKvs {
 
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
         let path = self.key_to_path(key);
-        track_any_err!(fs::create_dir_all(track_assert_some!(
-            path.parent(),
-            Failed
-        )))?;
-        let mut file = track_any_err!(OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path))?;
-        track_any_err!(file.write_all(value))?;
-        Ok(Existence::unknown())
+        let parent = track_assert_some!(path.parent(), Failed).to_path_buf();
+
+        // The directories whose entries change: the parent of the file, and the parents of
+        // the directories created for it.
+        let mut dirty_dirs = Vec::new();
+        track!(create_dir_all(&parent, &mut dirty_dirs))?;
+        dirty_dirs.push(parent);
+
+        let durable = self.write_strategy == FileSystemWriteStrategy::DurableRename;
+        let exists = match self.write_strategy {
+            FileSystemWriteStrategy::InPlace => track!(self.write_in_place(path, value))?,
+            FileSystemWriteStrategy::Rename => track!(self.write_via_rename(path, value, false))?,
+            FileSystemWriteStrategy::DurableRename => {
+                track!(self.write_via_rename(path, value, true))?
+            }
+        };
+        for dir in dirty_dirs {
+            if durable {
+                track!(sync_path(&dir))?;
+            } else {
+                self.mark_unsynced_dir(dir);
+            }
+        }
+        Ok(exists)
     }
 
     fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
@@ -76,7 +322,109 @@ impl KeyValueStore for FileSystemKvs {
 
     fn delete(&mut self, key: &[u8]) -> Result<Existence> {
         let path = self.key_to_path(key);
-        track_any_err!(fs::remove_file(path))?;
-        Ok(Existence::unknown())
+        match fs::remove_file(&path) {
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    track_any_err!(Err(e), "Cannot remove file: {:?}", path)?;
+                }
+                Ok(Existence::new(false))
+            }
+            Ok(()) => {
+                if let Some(parent) = path.parent() {
+                    self.mark_unsynced_dir(parent.to_path_buf());
+                }
+                if self.cleanup_empty_dirs {
+                    self.remove_empty_dirs(&path);
+                }
+                Ok(Existence::new(true))
+            }
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        // The files first, and then the directories that hold their entries.
+        for path in self.unsynced.drain() {
+            track!(sync_path(&path))?;
+        }
+        for dir in self.unsynced_dirs.drain() {
+            track!(sync_path(&dir))?;
+        }
+        Ok(())
+    }
+
+    fn take_probe_elapsed(&mut self) -> Option<Duration> {
+        if self.probe_existence {
+            Some(mem::take(&mut self.probe_elapsed))
+        } else {
+            None
+        }
+    }
+}
+
+/// Creates `dir` and its missing ancestors like `fs::create_dir_all`,
+/// and pushes the parents of the created directories to `dirty_dirs`.
+fn create_dir_all(dir: &Path, dirty_dirs: &mut Vec<PathBuf>) -> Result<()> {
+    if dir.is_dir() {
+        return Ok(());
+    }
+    let parent = track_assert_some!(dir.parent(), Failed; dir);
+    track!(create_dir_all(parent, dirty_dirs))?;
+    match fs::create_dir(dir) {
+        Ok(()) => dirty_dirs.push(parent.to_path_buf()),
+        Err(e) => {
+            if e.kind() != ErrorKind::AlreadyExists {
+                track_any_err!(Err(e), "Cannot create directory: {:?}", dir)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fsyncs a file or a directory, ignoring it if it has been removed.
+fn sync_path(path: &Path) -> Result<()> {
+    match File::open(path) {
+        Err(e) => {
+            if e.kind() != ErrorKind::NotFound {
+                track_any_err!(Err(e))?;
+            }
+        }
+        Ok(file) => {
+            track_any_err!(file.sync_all())?;
+        }
+    }
+    Ok(())
+}
+
+/// Escapes a leading `.` (which is never percent-encoded otherwise) as `%2E`.
+fn escape_leading_dot(component: &str) -> String {
+    if let Some(rest) = component.strip_prefix('.') {
+        format!("%2E{}", rest)
+    } else {
+        component.to_owned()
+    }
+}
+
+fn hash<H: Hasher>(name: &str, mut hasher: H) -> u64 {
+    name.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Debug)]
+struct Fnv1aHasher(u64);
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Fnv1aHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
     }
 }
@@ -0,0 +1,254 @@
+use crate::kvs::KeyValueStore;
+use crate::task::Existence;
+use crate::Result;
+use lmdb::{
+    Database, Environment, EnvironmentFlags, Error, RwTransaction, Transaction, WriteFlags,
+};
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use trackable::error::Failed;
+
+/// Options of `Lmdb`.
+#[derive(Debug, Clone)]
+pub struct LmdbOptions {
+    pub map_size: usize,
+    pub no_sync: bool,
+    pub no_meta_sync: bool,
+    pub write_map: bool,
+
+    /// The number of operations committed by one write transaction (`1` means one per operation).
+    pub ops_per_txn: usize,
+}
+impl Default for LmdbOptions {
+    fn default() -> Self {
+        LmdbOptions {
+            map_size: 1024 * 1024 * 1024,
+            no_sync: false,
+            no_meta_sync: false,
+            write_map: false,
+            ops_per_txn: 1,
+        }
+    }
+}
+
+/// LMDB store using the unnamed database of an environment.
+///
+/// When operations are grouped into one commit, the last group is committed by `sync`
+/// (or by `drop`, which can only report a commit failure on the standard error).
+/// A failed operation or commit rolls back the whole group, and the keys written by its
+/// earlier operations are reported by `take_discarded_keys`.
+pub struct Lmdb {
+    // A write transaction kept open across operations.
+    // It borrows `env`, so it must be declared (i.e., dropped) before that.
+    txn: Option<RwTransaction<'static>>,
+    uncommitted: usize,
+    ops_per_txn: usize,
+
+    // The keys written by the open transaction (only recorded when operations are grouped).
+    group_keys: Vec<Vec<u8>>,
+    discarded_keys: Vec<Vec<u8>>,
+    probe_existence: bool,
+    probe_elapsed: Duration,
+    db: Database,
+    env: Box<Environment>,
+}
+impl Lmdb {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        track!(Self::with_options(dir, LmdbOptions::default()))
+    }
+
+    pub fn with_options<P: AsRef<Path>>(dir: P, options: LmdbOptions) -> Result<Self> {
+        track_assert!(options.ops_per_txn > 0, Failed; options);
+        track_any_err!(fs::create_dir_all(&dir))?;
+
+        let mut flags = EnvironmentFlags::empty();
+        if options.no_sync {
+            flags.insert(EnvironmentFlags::NO_SYNC);
+        }
+        if options.no_meta_sync {
+            flags.insert(EnvironmentFlags::NO_META_SYNC);
+        }
+        if options.write_map {
+            flags.insert(EnvironmentFlags::WRITE_MAP);
+        }
+        let env = track_any_err!(Environment::new()
+            .set_map_size(options.map_size)
+            .set_flags(flags)
+            .open(dir.as_ref()))?;
+        let db = track_any_err!(env.open_db(None))?;
+        Ok(Lmdb {
+            txn: None,
+            uncommitted: 0,
+            ops_per_txn: options.ops_per_txn,
+            group_keys: Vec::new(),
+            discarded_keys: Vec::new(),
+            probe_existence: false,
+            probe_elapsed: Duration::default(),
+            db,
+            env: Box::new(env),
+        })
+    }
+
+    /// Makes `put` report accurate existence by trying a `NO_OVERWRITE` write beforehand.
+    ///
+    /// An existing key is written twice, and the time of the first (failed) write is
+    /// excluded from the latency of the operation.
+    pub fn enable_existence_probe(&mut self) {
+        self.probe_existence = true;
+    }
+
+    /// Commits the open write transaction (if any).
+    pub fn commit(&mut self) -> Result<()> {
+        if let Some(txn) = self.txn.take() {
+            track!(self.commit_txn(txn))?;
+        }
+        Ok(())
+    }
+
+    fn commit_txn(&mut self, txn: RwTransaction<'static>) -> Result<()> {
+        self.uncommitted = 0;
+        let result = txn.commit();
+        if result.is_ok() {
+            self.group_keys.clear();
+        } else {
+            self.discarded_keys.append(&mut self.group_keys);
+        }
+        track_any_err!(result)
+    }
+
+    fn begin_rw_txn(&self) -> Result<RwTransaction<'static>> {
+        let txn = track_any_err!(self.env.begin_rw_txn())?;
+
+        // SAFETY: The environment is never moved out of its box, and every transaction is
+        // committed, aborted or dropped (see the field order of `Lmdb`) before the box.
+        Ok(unsafe { mem::transmute::<RwTransaction<'_>, RwTransaction<'static>>(txn) })
+    }
+
+    fn write<F, T>(&mut self, key: &[u8], f: F) -> Result<T>
+    where
+        F: FnOnce(&mut RwTransaction<'static>, Database) -> Result<T>,
+    {
+        let mut txn = match self.txn.take() {
+            Some(txn) => txn,
+            None => track!(self.begin_rw_txn())?,
+        };
+        let value = match f(&mut txn, self.db) {
+            Ok(value) => value,
+            Err(e) => {
+                // LMDB transactions cannot be used after a failed operation.
+                let discarded = self.uncommitted;
+                self.uncommitted = 0;
+                self.discarded_keys.append(&mut self.group_keys);
+                txn.abort();
+                return Err(track!(
+                    e,
+                    "Aborted a transaction with {} uncommitted operations",
+                    discarded
+                ));
+            }
+        };
+        self.uncommitted += 1;
+        if self.ops_per_txn > 1 {
+            self.group_keys.push(key.to_vec());
+        }
+        if self.uncommitted < self.ops_per_txn {
+            self.txn = Some(txn);
+        } else {
+            track!(self.commit_txn(txn))?;
+        }
+        Ok(value)
+    }
+}
+impl fmt::Debug for Lmdb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lmdb")
+            .field("uncommitted", &self.uncommitted)
+            .field("ops_per_txn", &self.ops_per_txn)
+            .field("group_keys", &self.group_keys.len())
+            .field("discarded_keys", &self.discarded_keys.len())
+            .field("probe_existence", &self.probe_existence)
+            .field("probe_elapsed", &self.probe_elapsed)
+            .finish()
+    }
+}
+impl Drop for Lmdb {
+    fn drop(&mut self) {
+        // Commits the operations of the last group.
+        if let Err(e) = self.commit() {
+            eprintln!("Failed to commit the last LMDB transaction: {}", e);
+        }
+    }
+}
+impl KeyValueStore for Lmdb {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        if !self.probe_existence {
+            return track!(self.write(key, |txn, db| {
+                track_any_err!(txn.put(db, &key, &value, WriteFlags::empty()))?;
+                Ok(Existence::unknown())
+            }));
+        }
+
+        let mut probe_elapsed = Duration::default();
+        let result = track!(self.write(key, |txn, db| {
+            let start = Instant::now();
+            match txn.put(db, &key, &value, WriteFlags::NO_OVERWRITE) {
+                Ok(()) => Ok(Existence::new(false)),
+                Err(Error::KeyExist) => {
+                    probe_elapsed = start.elapsed();
+                    track_any_err!(txn.put(db, &key, &value, WriteFlags::empty()))?;
+                    Ok(Existence::new(true))
+                }
+                Err(e) => track_any_err!(Err(e)),
+            }
+        }));
+        self.probe_elapsed += probe_elapsed;
+        result
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        // Reads through the open write transaction (if any) to see its uncommitted writes.
+        let value = match &self.txn {
+            Some(txn) => txn.get(self.db, &key).map(<[u8]>::to_vec),
+            None => track_any_err!(self.env.begin_ro_txn())?
+                .get(self.db, &key)
+                .map(<[u8]>::to_vec),
+        };
+        match value {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => track_any_err!(Err(e)),
+        }
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        track!(self.write(key, |txn, db| match txn.del(db, &key, None) {
+            Ok(()) => Ok(Existence::new(true)),
+            Err(Error::NotFound) => Ok(Existence::new(false)),
+            Err(e) => track_any_err!(Err(e)),
+        }))
+    }
+
+    /// Commits the open write transaction (if any) and flushes the environment to disk.
+    fn sync(&mut self) -> Result<()> {
+        track!(self.commit())?;
+        track_any_err!(self.env.sync(true))?;
+        Ok(())
+    }
+
+    fn take_probe_elapsed(&mut self) -> Option<Duration> {
+        if self.probe_existence {
+            Some(mem::take(&mut self.probe_elapsed))
+        } else {
+            None
+        }
+    }
+
+    fn take_discarded_keys(&mut self) -> Vec<Vec<u8>> {
+        mem::take(&mut self.discarded_keys)
+    }
+}
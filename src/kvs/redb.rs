@@ -0,0 +1,92 @@
+use crate::kvs::KeyValueStore;
+use crate::task::Existence;
+use crate::Result;
+use redb::{Builder, Database, Durability, ReadableTable, TableDefinition};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("ekvsb");
+
+/// redb store that commits one write transaction per `put` and `delete`.
+pub struct Redb {
+    db: Database,
+    durability: Durability,
+}
+impl Redb {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        track!(Self::with_builder(path, Builder::new()))
+    }
+
+    pub fn with_builder<P: AsRef<Path>>(path: P, builder: Builder) -> Result<Self> {
+        if let Some(dir) = path.as_ref().parent() {
+            track_any_err!(fs::create_dir_all(dir))?;
+        }
+        let db = track_any_err!(builder.create(path))?;
+
+        // Creates the table beforehand, so that read transactions can always open it.
+        let txn = track_any_err!(db.begin_write())?;
+        track_any_err!(txn.open_table(TABLE))?;
+        track_any_err!(txn.commit())?;
+
+        Ok(Redb {
+            db,
+            durability: Durability::Immediate,
+        })
+    }
+
+    /// Sets the durability of the write transactions (the default is `Durability::Immediate`).
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+}
+impl fmt::Debug for Redb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Redb")
+            .field("durability", &self.durability)
+            .finish()
+    }
+}
+impl KeyValueStore for Redb {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        let mut txn = track_any_err!(self.db.begin_write())?;
+        txn.set_durability(self.durability);
+        let exists = {
+            let mut table = track_any_err!(txn.open_table(TABLE))?;
+            let old = track_any_err!(table.insert(key, value))?;
+            old.is_some()
+        };
+        track_any_err!(txn.commit())?;
+        Ok(Existence::new(exists))
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        let txn = track_any_err!(self.db.begin_read())?;
+        let table = track_any_err!(txn.open_table(TABLE))?;
+        let value = track_any_err!(table.get(key))?;
+        Ok(value.map(|v| v.value().to_vec()))
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        let mut txn = track_any_err!(self.db.begin_write())?;
+        txn.set_durability(self.durability);
+        let exists = {
+            let mut table = track_any_err!(txn.open_table(TABLE))?;
+            let old = track_any_err!(table.remove(key))?;
+            old.is_some()
+        };
+        track_any_err!(txn.commit())?;
+        Ok(Existence::new(exists))
+    }
+
+    /// Commits an empty write transaction with `Durability::Immediate`,
+    /// which also persists the preceding non-durable commits.
+    fn sync(&mut self) -> Result<()> {
+        let mut txn = track_any_err!(self.db.begin_write())?;
+        txn.set_durability(Durability::Immediate);
+        track_any_err!(txn.commit())?;
+        Ok(())
+    }
+}
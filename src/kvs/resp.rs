@@ -0,0 +1,319 @@
+//! A client backend for Redis-compatible servers speaking RESP.
+use crate::kvs::KeyValueStore;
+use crate::task::Existence;
+use crate::Result;
+use std::fmt;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use trackable::error::Failed;
+
+/// The maximum length of a bulk string (the default `proto-max-bulk-len` of Redis).
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// The maximum nesting depth of arrays.
+const MAX_DEPTH: usize = 32;
+
+/// RESP client issuing `SET`, `GET`, `MGET` and `DEL` commands.
+pub struct RespKvs {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: BufWriter<Box<dyn Write + Send>>,
+    set_get: bool,
+
+    // Set while a command is in flight, and left set if it fails before its reply has
+    // been read completely (the stream is no longer aligned to replies then).
+    broken: bool,
+}
+impl RespKvs {
+    pub fn connect_tcp<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = track_any_err!(TcpStream::connect(addr))?;
+        track_any_err!(stream.set_nodelay(true))?;
+        let reader = track_any_err!(stream.try_clone())?;
+        Ok(Self::new(Box::new(reader), Box::new(stream)))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let stream = track_any_err!(UnixStream::connect(path))?;
+        let reader = track_any_err!(stream.try_clone())?;
+        Ok(Self::new(Box::new(reader), Box::new(stream)))
+    }
+
+    fn new(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>) -> Self {
+        RespKvs {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+            set_get: false,
+            broken: false,
+        }
+    }
+
+    /// Makes `put` issue `SET key value GET` (Redis 6.2 or later) to report existence,
+    /// at the cost of transferring the old values.
+    pub fn enable_set_get(&mut self) {
+        self.set_get = true;
+    }
+
+    fn call(&mut self, args: &[&[u8]]) -> Result<Reply> {
+        track_assert!(!self.broken, Failed, "Broken by an earlier error");
+        self.broken = true;
+        track!(write_command(&mut self.writer, args))?;
+        track_any_err!(self.writer.flush())?;
+        let reply = track!(read_reply(&mut self.reader))?;
+        self.broken = false;
+        match reply {
+            Reply::Error(message) => track_panic!(Failed, "{}", String::from_utf8_lossy(&message)),
+            reply => Ok(reply),
+        }
+    }
+}
+impl fmt::Debug for RespKvs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RespKvs")
+            .field("set_get", &self.set_get)
+            .field("broken", &self.broken)
+            .finish()
+    }
+}
+impl KeyValueStore for RespKvs {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        if self.set_get {
+            match track!(self.call(&[&b"SET"[..], key, value, &b"GET"[..]]))? {
+                Reply::Bulk(old) => Ok(Existence::new(old.is_some())),
+                reply => track_panic!(Failed, "Unexpected reply: {:?}", reply),
+            }
+        } else {
+            match track!(self.call(&[&b"SET"[..], key, value]))? {
+                Reply::Status(_) => Ok(Existence::unknown()),
+                reply => track_panic!(Failed, "Unexpected reply: {:?}", reply),
+            }
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        match track!(self.call(&[&b"GET"[..], key]))? {
+            Reply::Bulk(value) => Ok(value),
+            reply => track_panic!(Failed, "Unexpected reply: {:?}", reply),
+        }
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        match track!(self.call(&[&b"DEL"[..], key]))? {
+            Reply::Integer(n) => Ok(Existence::new(n > 0)),
+            reply => track_panic!(Failed, "Unexpected reply: {:?}", reply),
+        }
+    }
+
+    fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Self::OwnedValue>>> {
+        let mut args = Vec::with_capacity(keys.len() + 1);
+        args.push(&b"MGET"[..]);
+        args.extend_from_slice(keys);
+        match track!(self.call(&args))? {
+            Reply::Array(Some(values)) => {
+                track_assert_eq!(values.len(), keys.len(), Failed);
+                values
+                    .into_iter()
+                    .map(|value| match value {
+                        Reply::Bulk(value) => Ok(value),
+                        reply => track_panic!(Failed, "Unexpected reply: {:?}", reply),
+                    })
+                    .collect()
+            }
+            reply => track_panic!(Failed, "Unexpected reply: {:?}", reply),
+        }
+    }
+}
+
+/// Serves `kvs` to a RESP client until the connection is closed.
+///
+/// Only `PING`, `SET` (with the optional `GET` flag), `GET`, `MGET` and `DEL` are supported,
+/// which is enough to stand in for a Redis server in tests.
+pub fn serve<T, R, W>(kvs: &mut T, reader: R, writer: W) -> Result<()>
+where
+    T: KeyValueStore,
+    R: Read,
+    W: Write,
+{
+    let mut reader = BufReader::new(reader);
+    let mut writer = BufWriter::new(writer);
+    while !track_any_err!(reader.fill_buf())?.is_empty() {
+        let args = match track!(read_reply(&mut reader))? {
+            Reply::Array(Some(args)) => track!(args
+                .into_iter()
+                .map(|arg| match arg {
+                    Reply::Bulk(Some(arg)) => Ok(arg),
+                    arg => track_panic!(Failed, "Malformed command argument: {:?}", arg),
+                })
+                .collect::<Result<Vec<_>>>())?,
+            reply => track_panic!(Failed, "Malformed command: {:?}", reply),
+        };
+        let reply = execute_command(kvs, &args).unwrap_or_else(|e| {
+            let message = format!("ERR {}", e).replace(|c: char| c == '\r' || c == '\n', " ");
+            Reply::Error(message.into_bytes())
+        });
+        track!(write_reply(&mut writer, &reply))?;
+        track_any_err!(writer.flush())?;
+    }
+    Ok(())
+}
+
+fn execute_command<T: KeyValueStore>(kvs: &mut T, args: &[Vec<u8>]) -> Result<Reply> {
+    let (name, args) = track_assert_some!(args.split_first(), Failed);
+    match (name.to_ascii_uppercase().as_slice(), args) {
+        (b"PING", []) => Ok(Reply::Status(b"PONG".to_vec())),
+        (b"SET", [key, value]) => {
+            track!(kvs.put(key, value))?;
+            Ok(Reply::Status(b"OK".to_vec()))
+        }
+        (b"SET", [key, value, flag]) if flag.eq_ignore_ascii_case(b"GET") => {
+            let old = track!(kvs.get(key))?.map(|v| v.as_ref().to_vec());
+            track!(kvs.put(key, value))?;
+            Ok(Reply::Bulk(old))
+        }
+        (b"GET", [key]) => {
+            let value = track!(kvs.get(key))?;
+            Ok(Reply::Bulk(value.map(|v| v.as_ref().to_vec())))
+        }
+        (b"MGET", keys) if !keys.is_empty() => {
+            let keys = keys.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let values = track!(kvs.multi_get(&keys))?;
+            let values = values
+                .into_iter()
+                .map(|v| Reply::Bulk(v.map(|v| v.as_ref().to_vec())))
+                .collect();
+            Ok(Reply::Array(Some(values)))
+        }
+        (b"DEL", keys) if !keys.is_empty() => {
+            let mut deleted = 0;
+            for key in keys {
+                if track!(kvs.delete(key))?.exists() == Some(true) {
+                    deleted += 1;
+                }
+            }
+            Ok(Reply::Integer(deleted))
+        }
+        _ => track_panic!(
+            Failed,
+            "Unsupported command: {:?}",
+            String::from_utf8_lossy(name)
+        ),
+    }
+}
+
+#[derive(Debug)]
+enum Reply {
+    Status(Vec<u8>),
+    Error(Vec<u8>),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+fn write_command<W: Write>(writer: &mut W, args: &[&[u8]]) -> Result<()> {
+    track_any_err!(write!(writer, "*{}\r\n", args.len()))?;
+    for arg in args {
+        track!(write_bulk(writer, arg))?;
+    }
+    Ok(())
+}
+
+fn write_reply<W: Write>(writer: &mut W, reply: &Reply) -> Result<()> {
+    match reply {
+        Reply::Status(s) => {
+            track_any_err!(writer.write_all(b"+"))?;
+            track_any_err!(writer.write_all(s))?;
+            track_any_err!(writer.write_all(b"\r\n"))?;
+        }
+        Reply::Error(s) => {
+            track_any_err!(writer.write_all(b"-"))?;
+            track_any_err!(writer.write_all(s))?;
+            track_any_err!(writer.write_all(b"\r\n"))?;
+        }
+        Reply::Integer(n) => track_any_err!(write!(writer, ":{}\r\n", n))?,
+        Reply::Bulk(None) => track_any_err!(writer.write_all(b"$-1\r\n"))?,
+        Reply::Bulk(Some(bytes)) => track!(write_bulk(writer, bytes))?,
+        Reply::Array(None) => track_any_err!(writer.write_all(b"*-1\r\n"))?,
+        Reply::Array(Some(items)) => {
+            track_any_err!(write!(writer, "*{}\r\n", items.len()))?;
+            for item in items {
+                track!(write_reply(writer, item))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_bulk<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    track_any_err!(write!(writer, "${}\r\n", bytes.len()))?;
+    track_any_err!(writer.write_all(bytes))?;
+    track_any_err!(writer.write_all(b"\r\n"))?;
+    Ok(())
+}
+
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<Reply> {
+    track!(read_nested_reply(reader, 0))
+}
+
+/// Reads a reply nested in `depth` arrays.
+///
+/// Lengths in headers are not trusted: nothing is allocated before the data arrives.
+fn read_nested_reply<R: BufRead>(reader: &mut R, depth: usize) -> Result<Reply> {
+    let line = track!(read_line(reader))?;
+    let (kind, rest) = track_assert_some!(line.split_first(), Failed);
+    match *kind {
+        b'+' => Ok(Reply::Status(rest.to_vec())),
+        b'-' => Ok(Reply::Error(rest.to_vec())),
+        b':' => Ok(Reply::Integer(track!(parse_int(rest))?)),
+        b'$' => {
+            let len = track!(parse_int(rest))?;
+            if len < 0 {
+                return Ok(Reply::Bulk(None));
+            }
+            track_assert!(len <= MAX_BULK_LEN, Failed, "Too long bulk string: {}", len);
+            let mut buf = Vec::new();
+            track_any_err!(reader.take(len as u64 + 2).read_to_end(&mut buf))?;
+            track_assert_eq!(buf.len() as i64, len + 2, Failed, "Unexpected EOF");
+            track_assert!(buf.ends_with(b"\r\n"), Failed, "Malformed bulk string");
+            buf.truncate(len as usize);
+            Ok(Reply::Bulk(Some(buf)))
+        }
+        b'*' => {
+            let len = track!(parse_int(rest))?;
+            if len < 0 {
+                return Ok(Reply::Array(None));
+            }
+            track_assert!(depth < MAX_DEPTH, Failed, "Too deeply nested arrays");
+            let mut items = Vec::new();
+            for _ in 0..len {
+                items.push(track!(read_nested_reply(reader, depth + 1))?);
+            }
+            Ok(Reply::Array(Some(items)))
+        }
+        // RESP3 null.
+        b'_' => Ok(Reply::Bulk(None)),
+        _ => track_panic!(Failed, "Unknown reply type: {:?}", *kind as char),
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut line = Vec::new();
+    track_any_err!(reader.read_until(b'\n', &mut line))?;
+    track_assert!(
+        line.ends_with(b"\r\n"),
+        Failed,
+        "Connection closed or malformed line: {:?}",
+        line
+    );
+    line.truncate(line.len() - 2);
+    Ok(line)
+}
+
+fn parse_int(bytes: &[u8]) -> Result<i64> {
+    let s = track_any_err!(std::str::from_utf8(bytes))?;
+    track_any_err!(s.parse())
+}
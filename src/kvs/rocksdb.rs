@@ -1,40 +1,491 @@
 use crate::kvs::KeyValueStore;
-use crate::task::Existence;
+use crate::task::{Existence, Metadata, UpdateOp};
 use crate::Result;
-use rocksdb::{Options, DB};
-use std::path::Path;
+use rocksdb::{
+    Cache, ColumnFamily, ColumnFamilyDescriptor, Env, MergeOperands, Options, ReadOptions,
+    WriteOptions, DB,
+};
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use trackable::error::Failed;
+
+/// The size of the block cache RocksDB creates for a table without one.
+const DEFAULT_BLOCK_CACHE_SIZE: usize = 32 * 1024 * 1024;
+
+/// A column family and the key prefix routed to it.
+pub struct RocksDbColumnFamily {
+    pub name: String,
+
+    /// Keys starting with this prefix (kept as a part of the key) are stored in this family.
+    ///
+    /// If several prefixes match a key, the longest one wins.
+    /// Keys matching no prefix are stored in the default column family.
+    pub prefix: Vec<u8>,
+
+    pub options: Options,
+}
 
-#[derive(Debug)]
 pub struct RocksDb {
     db: DB,
+    options: Options,
+    path: PathBuf,
+    routes: Vec<(Vec<u8>, String)>,
+    write_options: WriteOptions,
+    wal_disabled: bool,
+    read_options: ReadOptions,
+    probe_existence: bool,
+    probe_elapsed: Duration,
+    ops: u64,
+    statistics_interval: Option<u64>,
+    statistics_due: bool,
+    statistics_history: Vec<Statistics>,
 }
 impl RocksDb {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let db = track_any_err!(DB::open_default(path))?;
-        Ok(Self { db })
+        track!(Self::with_options(path, Options::default()))
     }
 
-    pub fn with_options<P: AsRef<Path>>(path: P, mut options: Options) -> Result<Self> {
+    pub fn with_options<P: AsRef<Path>>(path: P, options: Options) -> Result<Self> {
+        track!(Self::with_column_families(path, options, Vec::new()))
+    }
+
+    pub fn with_column_families<P: AsRef<Path>>(
+        path: P,
+        mut options: Options,
+        column_families: Vec<RocksDbColumnFamily>,
+    ) -> Result<Self> {
         options.create_if_missing(true);
-        let db = track_any_err!(DB::open(&options, path))?;
-        Ok(Self { db })
+        options.create_missing_column_families(true);
+        set_update_merge_operator(&mut options);
+
+        let mut routes = Vec::new();
+        let mut descriptors = Vec::new();
+        for mut cf in column_families {
+            set_update_merge_operator(&mut cf.options);
+            routes.push((cf.prefix, cf.name.clone()));
+            descriptors.push(ColumnFamilyDescriptor::new(cf.name, cf.options));
+        }
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        let db = track_any_err!(DB::open_cf_descriptors(&options, &path, descriptors))?;
+        let mut this = Self::from_db(db, options, path);
+        this.routes = routes;
+        Ok(this)
+    }
+
+    /// Loads a native RocksDB options file (e.g., `OPTIONS-000005` in a DB directory).
+    ///
+    /// The block cache is sized by the `block_cache` entry of the file if any,
+    /// and otherwise by the RocksDB default.
+    pub fn load_options_file<P: AsRef<Path>>(path: P) -> Result<Options> {
+        let content = track_any_err!(
+            fs::read_to_string(&path),
+            "Cannot read options file: {:?}",
+            path.as_ref()
+        )?;
+        let capacity = track!(block_cache_capacity(&content))?.unwrap_or(DEFAULT_BLOCK_CACHE_SIZE);
+
+        // `Options::load_latest` only takes a DB directory,
+        // so the file is copied into a temporary one (removed when dropped).
+        let dir = track_any_err!(TempDir::new())?;
+        track_any_err!(fs::write(dir.path().join("OPTIONS-000001"), &content))?;
+        let env = track_any_err!(Env::new())?;
+
+        // The given cache replaces the block cache of every loaded table factory.
+        let cache = Cache::new_lru_cache(capacity);
+        let (options, _) = track_any_err!(Options::load_latest(dir.path(), env, false, cache))?;
+        Ok(options)
+    }
+
+    /// Sets the options applied to every `put` and `delete` (this undoes `disable_wal`).
+    pub fn set_write_options(&mut self, options: WriteOptions) {
+        self.write_options = options;
+        self.wal_disabled = false;
+    }
+
+    /// Makes every `put` and `delete` skip the WAL.
+    ///
+    /// Since there is no WAL to flush, `sync` then flushes the memtables instead.
+    pub fn disable_wal(&mut self) {
+        self.write_options.disable_wal(true);
+        self.wal_disabled = true;
+    }
+
+    /// Sets the options applied to every `get`.
+    pub fn set_read_options(&mut self, options: ReadOptions) {
+        self.read_options = options;
+    }
+
+    /// Makes `put` and `delete` report accurate existence by looking up keys beforehand.
+    pub fn enable_existence_probe(&mut self) {
+        self.probe_existence = true;
+    }
+
+    /// Collects statistics every `ops` operations in addition to the end of the run.
+    ///
+    /// Statistics are collected only if `Options::enable_statistics` has been called.
+    /// The collection is deferred to `KeyValueStore::after_task`, so that its time is not
+    /// included in the latency of the triggering operation.
+    pub fn set_statistics_interval(&mut self, ops: u64) {
+        self.statistics_interval = Some(ops);
+    }
+
+    fn from_db<P: AsRef<Path>>(db: DB, options: Options, path: P) -> Self {
+        Self {
+            db,
+            options,
+            path: path.as_ref().to_path_buf(),
+            routes: Vec::new(),
+            write_options: WriteOptions::default(),
+            wal_disabled: false,
+            read_options: ReadOptions::default(),
+            probe_existence: false,
+            probe_elapsed: Duration::default(),
+            ops: 0,
+            statistics_interval: None,
+            statistics_due: false,
+            statistics_history: Vec::new(),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ops += 1;
+        if let Some(interval) = self.statistics_interval {
+            if self.ops % interval == 0 {
+                self.statistics_due = true;
+            }
+        }
+    }
+
+    fn collect_statistics(&self) -> Result<Option<Statistics>> {
+        let tickers = if let Some(tickers) = self.options.get_statistics() {
+            tickers
+        } else {
+            return Ok(None);
+        };
+        let ticker = |name| parse_ticker(&tickers, name);
+
+        let mut sst_files = 0;
+        for level in 0.. {
+            let property = format!("rocksdb.num-files-at-level{}", level);
+            match track_any_err!(self.db.property_value(property.as_str()))? {
+                None => break,
+                Some(n) => sst_files += track_any_err!(n.trim().parse::<u64>())?,
+            }
+        }
+
+        let hits = ticker("rocksdb.block.cache.hit");
+        let misses = ticker("rocksdb.block.cache.miss");
+        Ok(Some(Statistics {
+            ops: self.ops,
+            compaction_read_bytes: ticker("rocksdb.compact.read.bytes"),
+            compaction_write_bytes: ticker("rocksdb.compact.write.bytes"),
+            stall_micros: ticker("rocksdb.stall.micros"),
+            block_cache_hit_ratio: if hits + misses > 0 {
+                Some(hits as f64 / (hits + misses) as f64)
+            } else {
+                None
+            },
+            sst_files,
+            live_sst_files_size: track_any_err!(self
+                .db
+                .property_int_value("rocksdb.live-sst-files-size"))?,
+            estimate_pending_compaction_bytes: track_any_err!(self
+                .db
+                .property_int_value("rocksdb.estimate-pending-compaction-bytes"))?,
+        }))
+    }
+
+    fn column_family(&self, key: &[u8]) -> Option<&ColumnFamily> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| key.starts_with(prefix))
+            .and_then(|(_, name)| self.db.cf_handle(name))
+    }
+
+    fn probe(&mut self, key: &[u8]) -> Result<Existence> {
+        if !self.probe_existence {
+            return Ok(Existence::unknown());
+        }
+        let start = Instant::now();
+        let exists = match self.column_family(key) {
+            Some(cf) => {
+                self.db.key_may_exist_cf(cf, key)
+                    && track_any_err!(self.db.get_pinned_cf_opt(cf, key, &self.read_options))?
+                        .is_some()
+            }
+            None => {
+                self.db.key_may_exist(key)
+                    && track_any_err!(self.db.get_pinned_opt(key, &self.read_options))?.is_some()
+            }
+        };
+        self.probe_elapsed += start.elapsed();
+        Ok(Existence::new(exists))
+    }
+}
+impl fmt::Debug for RocksDb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RocksDb")
+            .field("db", &self.db)
+            .field("path", &self.path)
+            .field("routes", &self.routes)
+            .field("probe_existence", &self.probe_existence)
+            .field("probe_elapsed", &self.probe_elapsed)
+            .finish()
     }
 }
 impl KeyValueStore for RocksDb {
     type OwnedValue = Vec<u8>;
 
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
-        track_any_err!(self.db.put(key, value))?;
-        Ok(Existence::unknown())
+        let exists = track!(self.probe(key))?;
+        match self.column_family(key) {
+            Some(cf) => track_any_err!(self.db.put_cf_opt(cf, key, value, &self.write_options))?,
+            None => track_any_err!(self.db.put_opt(key, value, &self.write_options))?,
+        }
+        self.tick();
+        Ok(exists)
     }
 
     fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
-        let value = track_any_err!(self.db.get(key))?;
+        let value = match self.column_family(key) {
+            Some(cf) => track_any_err!(self.db.get_cf_opt(cf, key, &self.read_options))?,
+            None => track_any_err!(self.db.get_opt(key, &self.read_options))?,
+        };
+        self.tick();
         Ok(value)
     }
 
+    fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Self::OwnedValue>>> {
+        let values = if self.routes.is_empty() {
+            self.db
+                .multi_get_opt(keys.iter().copied(), &self.read_options)
+        } else {
+            let default = track_assert_some!(self.db.cf_handle("default"), Failed);
+            let cf_keys = keys
+                .iter()
+                .map(|key| (self.column_family(key).unwrap_or(default), *key))
+                .collect::<Vec<_>>();
+            self.db.multi_get_cf_opt(cf_keys, &self.read_options)
+        };
+        let values = track_any_err!(values
+            .into_iter()
+            .collect::<std::result::Result<Vec<_>, _>>())?;
+        self.tick();
+        Ok(values)
+    }
+
     fn delete(&mut self, key: &[u8]) -> Result<Existence> {
-        track_any_err!(self.db.delete(key))?;
-        Ok(Existence::unknown())
+        let exists = track!(self.probe(key))?;
+        match self.column_family(key) {
+            Some(cf) => track_any_err!(self.db.delete_cf_opt(cf, key, &self.write_options))?,
+            None => track_any_err!(self.db.delete_opt(key, &self.write_options))?,
+        }
+        self.tick();
+        Ok(exists)
+    }
+
+    /// Issues a merge that is resolved by the `ekvsb.update` merge operator on reads and compactions.
+    fn update(&mut self, key: &[u8], op: &UpdateOp) -> Result<Existence> {
+        let exists = track!(self.probe(key))?;
+        let operand = encode_update_op(op);
+        match self.column_family(key) {
+            Some(cf) => {
+                track_any_err!(self.db.merge_cf_opt(cf, key, operand, &self.write_options))?
+            }
+            None => track_any_err!(self.db.merge_opt(key, operand, &self.write_options))?,
+        }
+        self.tick();
+        Ok(exists)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if self.wal_disabled {
+            track_any_err!(self.db.flush())?;
+            for (_, name) in &self.routes {
+                let cf = track_assert_some!(self.db.cf_handle(name), Failed; name);
+                track_any_err!(self.db.flush_cf(cf))?;
+            }
+        } else {
+            track_any_err!(self.db.flush_wal(true))?;
+        }
+        Ok(())
     }
+
+    fn take_probe_elapsed(&mut self) -> Option<Duration> {
+        if self.probe_existence {
+            Some(mem::take(&mut self.probe_elapsed))
+        } else {
+            None
+        }
+    }
+
+    fn after_task(&mut self) -> Result<()> {
+        if self.statistics_due {
+            self.statistics_due = false;
+            if let Some(statistics) = track!(self.collect_statistics())? {
+                self.statistics_history.push(statistics);
+            }
+        }
+        Ok(())
+    }
+
+    fn metadata(&mut self) -> Result<Metadata> {
+        let mut metadata = Metadata::new();
+        if let Some(options) = track!(read_latest_options_file(&self.path))? {
+            metadata.insert("rocksdb_options".to_owned(), options.into());
+        }
+        if let Some(statistics) = track!(self.collect_statistics())? {
+            let statistics = track_any_err!(serde_json::to_value(statistics))?;
+            metadata.insert("rocksdb_statistics".to_owned(), statistics);
+            if let Some(stats) = track_any_err!(self.db.property_value("rocksdb.stats"))? {
+                metadata.insert("rocksdb_stats".to_owned(), stats.into());
+            }
+        }
+        if !self.statistics_history.is_empty() {
+            let history = track_any_err!(serde_json::to_value(&self.statistics_history))?;
+            metadata.insert("rocksdb_statistics_history".to_owned(), history);
+        }
+        Ok(metadata)
+    }
+}
+
+/// Installs the merge operator used by `KeyValueStore::update`.
+fn set_update_merge_operator(options: &mut Options) {
+    options.set_merge_operator("ekvsb.update", full_merge, partial_merge);
+}
+
+fn full_merge(_key: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    let mut value = existing.map(<[u8]>::to_vec);
+    for operand in operands {
+        let op = decode_update_op(operand)?;
+        value = Some(op.apply(value.as_deref()));
+    }
+    value
+}
+
+fn partial_merge(
+    _key: &[u8],
+    _existing: Option<&[u8]>,
+    _operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    // Operands are kept as they are until a full merge.
+    None
+}
+
+fn encode_update_op(op: &UpdateOp) -> Vec<u8> {
+    match op {
+        UpdateOp::Add(n) => {
+            let mut operand = vec![0];
+            operand.extend_from_slice(&n.to_be_bytes());
+            operand
+        }
+        UpdateOp::Append(bytes) => {
+            let mut operand = vec![1];
+            operand.extend_from_slice(bytes);
+            operand
+        }
+    }
+}
+
+fn decode_update_op(operand: &[u8]) -> Option<UpdateOp> {
+    match operand.split_first()? {
+        (0, n) if n.len() == 8 => {
+            let mut buf = [0; 8];
+            buf.copy_from_slice(n);
+            Some(UpdateOp::Add(u64::from_be_bytes(buf)))
+        }
+        (1, bytes) => Some(UpdateOp::Append(bytes.to_vec())),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Statistics {
+    ops: u64,
+    compaction_read_bytes: u64,
+    compaction_write_bytes: u64,
+    stall_micros: u64,
+    block_cache_hit_ratio: Option<f64>,
+    sst_files: u64,
+    live_sst_files_size: Option<u64>,
+    estimate_pending_compaction_bytes: Option<u64>,
+}
+
+/// Extracts a ticker count from the output of `Options::get_statistics`.
+///
+/// Each ticker is formatted as `rocksdb.block.cache.hit COUNT : 10`.
+fn parse_ticker(statistics: &str, name: &str) -> u64 {
+    statistics
+        .lines()
+        .filter_map(|line| line.strip_prefix(name))
+        .filter_map(|rest| rest.trim().strip_prefix("COUNT :"))
+        .find_map(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Reads the options file that RocksDB persisted in `dir` for the current DB instance.
+fn read_latest_options_file(dir: &Path) -> Result<Option<String>> {
+    let mut latest = None;
+    for entry in track_any_err!(fs::read_dir(dir))? {
+        let entry = track_any_err!(entry)?;
+        let name = entry.file_name();
+        let number = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("OPTIONS-"))
+            .and_then(|number| number.parse::<u64>().ok());
+        if let Some(number) = number {
+            if latest.as_ref().map_or(true, |(n, _)| *n < number) {
+                latest = Some((number, entry.path()));
+            }
+        }
+    }
+    if let Some((_, path)) = latest {
+        let options = track_any_err!(fs::read_to_string(path))?;
+        Ok(Some(options))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns the capacity of the `block_cache` entry (e.g., `block_cache=64M` or
+/// `block_cache={capacity=64M;num_shard_bits=6}`) of the first block-based table section.
+fn block_cache_capacity(options_file: &str) -> Result<Option<usize>> {
+    let mut in_block_based_table = false;
+    for line in options_file.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_block_based_table = line.starts_with("[TableOptions/BlockBasedTable");
+            continue;
+        }
+        if !in_block_based_table {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("block_cache=") {
+            let value = value.trim_matches(|c| c == '{' || c == '}');
+            let capacity = value
+                .split(';')
+                .find_map(|entry| entry.strip_prefix("capacity="))
+                .unwrap_or(value);
+            return track!(parse_rocksdb_size(capacity.trim())).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Parses a size in the RocksDB options syntax (a number with an optional `k`, `m`, `g` or `t`).
+fn parse_rocksdb_size(s: &str) -> Result<usize> {
+    let (digits, shift) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 10),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 20),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 30),
+        Some('t') | Some('T') => (&s[..s.len() - 1], 40),
+        _ => (s, 0),
+    };
+    let n: usize = track_any_err!(digits.parse(), "Malformed size: {:?}", s)?;
+    let unit = track_assert_some!(1usize.checked_shl(shift), Failed; s);
+    Ok(track_assert_some!(n.checked_mul(unit), Failed; s))
 }
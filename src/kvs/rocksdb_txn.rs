@@ -0,0 +1,235 @@
+use crate::kvs::KeyValueStore;
+use crate::task::{Existence, TxnOutcome};
+use crate::Result;
+use rocksdb::{
+    ErrorKind, OptimisticTransactionDB, OptimisticTransactionOptions, Options, ReadOptions,
+    Transaction, TransactionDB, TransactionDBOptions, TransactionOptions, WriteOptions,
+};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use trackable::error::{ErrorKindExt, Failed};
+
+const DEFAULT_MAX_RETRIES: usize = 10;
+
+/// RocksDB `TransactionDB` (pessimistic locking).
+///
+/// A transaction that times out waiting for a lock is retried like a commit conflict.
+/// `sync` is not supported, since `TransactionDB` exposes no way to flush the WAL;
+/// use `set_write_options` with `sync` instead.
+pub struct RocksDbTxn {
+    db: Arc<TransactionDB>,
+    max_retries: usize,
+    write_options: WriteOptions,
+    read_options: ReadOptions,
+    txn_options: TransactionOptions,
+}
+impl RocksDbTxn {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        track!(Self::with_options(path, Options::default()))
+    }
+
+    pub fn with_options<P: AsRef<Path>>(path: P, mut options: Options) -> Result<Self> {
+        options.create_if_missing(true);
+        let db = track_any_err!(TransactionDB::open(
+            &options,
+            &TransactionDBOptions::default(),
+            path
+        ))?;
+        Ok(RocksDbTxn {
+            db: Arc::new(db),
+            max_retries: DEFAULT_MAX_RETRIES,
+            write_options: WriteOptions::default(),
+            read_options: ReadOptions::default(),
+            txn_options: TransactionOptions::default(),
+        })
+    }
+
+    /// Sets how many times a transaction is retried on commit conflicts.
+    pub fn set_max_retries(&mut self, max_retries: usize) {
+        self.max_retries = max_retries;
+    }
+
+    /// Sets the options applied to every write (including transaction commits).
+    pub fn set_write_options(&mut self, options: WriteOptions) {
+        self.write_options = options;
+    }
+
+    /// Sets the options applied to every read (including reads in transactions).
+    pub fn set_read_options(&mut self, options: ReadOptions) {
+        self.read_options = options;
+    }
+
+    /// Sets how long a transaction waits for a lock held by another transaction.
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.txn_options
+            .set_lock_timeout(timeout.as_millis() as i64);
+    }
+
+    /// Returns the underlying DB (e.g., to run conflicting transactions besides this store).
+    pub fn db(&self) -> Arc<TransactionDB> {
+        Arc::clone(&self.db)
+    }
+}
+impl KeyValueStore for RocksDbTxn {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        track_any_err!(self.db.put_opt(key, value, &self.write_options))?;
+        Ok(Existence::unknown())
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        let value = track_any_err!(self.db.get_opt(key, &self.read_options))?;
+        Ok(value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        track_any_err!(self.db.delete_opt(key, &self.write_options))?;
+        Ok(Existence::unknown())
+    }
+
+    fn transaction(&mut self, keys: &[&[u8]], value: &[u8]) -> Result<TxnOutcome> {
+        let this = &*self;
+        track!(retry(this.max_retries, || read_modify_write(
+            this.db
+                .transaction_opt(&this.write_options, &this.txn_options),
+            &this.read_options,
+            keys,
+            value
+        )))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        track_panic!(
+            Failed,
+            "RocksDB TransactionDB cannot sync (use synchronous writes instead)"
+        )
+    }
+}
+
+/// RocksDB `OptimisticTransactionDB` (validation at commit time).
+pub struct RocksDbOptimistic {
+    db: OptimisticTransactionDB,
+    max_retries: usize,
+    write_options: WriteOptions,
+    read_options: ReadOptions,
+    wal_disabled: bool,
+}
+impl RocksDbOptimistic {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        track!(Self::with_options(path, Options::default()))
+    }
+
+    pub fn with_options<P: AsRef<Path>>(path: P, mut options: Options) -> Result<Self> {
+        options.create_if_missing(true);
+        let db = track_any_err!(OptimisticTransactionDB::open(&options, path))?;
+        Ok(RocksDbOptimistic {
+            db,
+            max_retries: DEFAULT_MAX_RETRIES,
+            write_options: WriteOptions::default(),
+            read_options: ReadOptions::default(),
+            wal_disabled: false,
+        })
+    }
+
+    /// Sets how many times a transaction is retried on commit conflicts.
+    pub fn set_max_retries(&mut self, max_retries: usize) {
+        self.max_retries = max_retries;
+    }
+
+    /// Sets the options applied to every write (including transaction commits).
+    ///
+    /// This undoes `disable_wal`.
+    pub fn set_write_options(&mut self, options: WriteOptions) {
+        self.write_options = options;
+        self.wal_disabled = false;
+    }
+
+    /// Sets the options applied to every read (including reads in transactions).
+    pub fn set_read_options(&mut self, options: ReadOptions) {
+        self.read_options = options;
+    }
+
+    /// Makes every write skip the WAL.
+    ///
+    /// Since there is no WAL to flush, `sync` then flushes the memtables instead.
+    pub fn disable_wal(&mut self) {
+        self.write_options.disable_wal(true);
+        self.wal_disabled = true;
+    }
+}
+impl KeyValueStore for RocksDbOptimistic {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        track_any_err!(self.db.put_opt(key, value, &self.write_options))?;
+        Ok(Existence::unknown())
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        let value = track_any_err!(self.db.get_opt(key, &self.read_options))?;
+        Ok(value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        track_any_err!(self.db.delete_opt(key, &self.write_options))?;
+        Ok(Existence::unknown())
+    }
+
+    fn transaction(&mut self, keys: &[&[u8]], value: &[u8]) -> Result<TxnOutcome> {
+        let this = &*self;
+        let txn_options = OptimisticTransactionOptions::default();
+        track!(retry(this.max_retries, || read_modify_write(
+            this.db.transaction_opt(&this.write_options, &txn_options),
+            &this.read_options,
+            keys,
+            value
+        )))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if self.wal_disabled {
+            track_any_err!(self.db.flush())?;
+        } else {
+            track_any_err!(self.db.flush_wal(true))?;
+        }
+        Ok(())
+    }
+}
+
+fn retry<F>(max_retries: usize, mut f: F) -> Result<TxnOutcome>
+where
+    F: FnMut() -> std::result::Result<(), rocksdb::Error>,
+{
+    let mut retries = 0;
+    loop {
+        match f() {
+            Ok(()) => return Ok(TxnOutcome::Committed { retries }),
+            Err(e) => match e.kind() {
+                ErrorKind::Busy | ErrorKind::TryAgain | ErrorKind::TimedOut => {
+                    if retries == max_retries {
+                        return Ok(TxnOutcome::Conflicted { retries });
+                    }
+                    retries += 1;
+                }
+                _ => return Err(track!(Failed.cause(e).into())),
+            },
+        }
+    }
+}
+
+fn read_modify_write<D>(
+    txn: Transaction<D>,
+    read_options: &ReadOptions,
+    keys: &[&[u8]],
+    value: &[u8],
+) -> std::result::Result<(), rocksdb::Error> {
+    for key in keys {
+        txn.get_for_update_opt(key, true, read_options)?;
+    }
+    for key in keys {
+        txn.put(key, value)?;
+    }
+    txn.commit()
+}
@@ -1,8 +1,11 @@
 use crate::kvs::KeyValueStore;
-use crate::task::Existence;
+use crate::task::{CasOutcome, Existence, TxnOutcome, UpdateOp};
 use crate::Result;
+use sled::transaction::{ConflictableTransactionResult, TransactionError, TransactionalTree};
 use sled::{Config, Db, IVec};
+use std::cell::Cell;
 use std::path::Path;
+use trackable::error::Failed;
 
 // #[derive(Debug)]
 pub struct SledTree {
@@ -10,7 +13,11 @@ pub struct SledTree {
 }
 impl SledTree {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let tree = track_any_err!(Config::new().path(path).open())?;
+        track!(Self::with_config(path, Config::new()))
+    }
+
+    pub fn with_config<P: AsRef<Path>>(path: P, config: Config) -> Result<Self> {
+        let tree = track_any_err!(config.path(path).open())?;
         Ok(SledTree { tree })
     }
 }
@@ -18,7 +25,7 @@ impl KeyValueStore for SledTree {
     type OwnedValue = IVec;
 
     fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
-        track_any_err!(self.tree.insert(key.to_vec(), value.to_vec()))?;
+        track_any_err!(self.tree.insert(key, value))?;
         Ok(Existence::unknown())
     }
 
@@ -31,4 +38,56 @@ impl KeyValueStore for SledTree {
         let exists = track_any_err!(self.tree.remove(key))?.is_some();
         Ok(Existence::new(exists))
     }
+
+    fn update(&mut self, key: &[u8], op: &UpdateOp) -> Result<Existence> {
+        // The closure may be called several times; the last call reflects the stored value.
+        let exists = Cell::new(false);
+        track_any_err!(self.tree.update_and_fetch(key, |old| {
+            exists.set(old.is_some());
+            Some(op.apply(old))
+        }))?;
+        Ok(Existence::new(exists.get()))
+    }
+
+    fn transaction(&mut self, keys: &[&[u8]], value: &[u8]) -> Result<TxnOutcome> {
+        // sled retries conflicting transactions internally, so attempts are counted here.
+        let attempts = Cell::new(0);
+        let result = self.tree.transaction(|tx: &TransactionalTree| {
+            attempts.set(attempts.get() + 1);
+            for key in keys {
+                tx.get(key)?;
+            }
+            for key in keys {
+                tx.insert(*key, value)?;
+            }
+            let result: ConflictableTransactionResult<(), ()> = Ok(());
+            result
+        });
+        match result {
+            Ok(()) => Ok(TxnOutcome::Committed {
+                retries: attempts.get() - 1,
+            }),
+            Err(TransactionError::Abort(())) => {
+                track_panic!(Failed, "Unexpected transaction abort")
+            }
+            Err(TransactionError::Storage(e)) => track_any_err!(Err(e)),
+        }
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        value: &[u8],
+    ) -> Result<CasOutcome> {
+        match track_any_err!(self.tree.compare_and_swap(key, expected, Some(value)))? {
+            Ok(()) => Ok(CasOutcome::Swapped),
+            Err(_) => Ok(CasOutcome::Failed),
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        track_any_err!(self.tree.flush())?;
+        Ok(())
+    }
 }
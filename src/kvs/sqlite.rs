@@ -0,0 +1,170 @@
+use crate::kvs::KeyValueStore;
+use crate::task::Existence;
+use crate::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::cmp;
+use std::fs;
+use std::path::Path;
+use trackable::error::Failed;
+
+const PUT_SQL: &str = "INSERT INTO kvs (key, value) VALUES (?1, ?2) \
+                       ON CONFLICT(key) DO UPDATE SET value = excluded.value";
+const GET_SQL: &str = "SELECT value FROM kvs WHERE key = ?1";
+const DELETE_SQL: &str = "DELETE FROM kvs WHERE key = ?1";
+
+/// Options of `Sqlite` (`None` keeps the SQLite default).
+#[derive(Debug, Clone, Default)]
+pub struct SqliteOptions {
+    pub journal_mode: Option<SqliteJournalMode>,
+    pub synchronous: Option<SqliteSynchronous>,
+
+    /// Page size in bytes (only effective when the database is created).
+    pub page_size: Option<u32>,
+
+    /// Page cache size in bytes (rounded up to a multiple of 1 KiB).
+    pub cache_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqliteJournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+impl SqliteJournalMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            SqliteJournalMode::Delete => "delete",
+            SqliteJournalMode::Truncate => "truncate",
+            SqliteJournalMode::Persist => "persist",
+            SqliteJournalMode::Memory => "memory",
+            SqliteJournalMode::Wal => "wal",
+            SqliteJournalMode::Off => "off",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqliteSynchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+impl SqliteSynchronous {
+    fn as_str(self) -> &'static str {
+        match self {
+            SqliteSynchronous::Off => "off",
+            SqliteSynchronous::Normal => "normal",
+            SqliteSynchronous::Full => "full",
+            SqliteSynchronous::Extra => "extra",
+        }
+    }
+}
+
+/// SQLite store keeping pairs in a `(key BLOB PRIMARY KEY, value BLOB)` table.
+///
+/// Every operation is an auto-committed statement, prepared once and then taken from
+/// the statement cache of the connection.
+#[derive(Debug)]
+pub struct Sqlite {
+    conn: Connection,
+
+    /// The effective `synchronous` level (`0` is `off` and `1` is `normal`).
+    synchronous: i64,
+    wal: bool,
+}
+impl Sqlite {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        track!(Self::with_options(path, SqliteOptions::default()))
+    }
+
+    pub fn with_options<P: AsRef<Path>>(path: P, options: SqliteOptions) -> Result<Self> {
+        if let Some(dir) = path.as_ref().parent() {
+            track_any_err!(fs::create_dir_all(dir))?;
+        }
+        let conn = track_any_err!(Connection::open(path))?;
+
+        // `page_size` has to precede both the first write and a switch to WAL mode.
+        if let Some(v) = options.page_size {
+            track_any_err!(conn.pragma_update(None, "page_size", v))?;
+        }
+        if let Some(v) = options.journal_mode {
+            let mode: String = track_any_err!(conn.pragma_update_and_check(
+                None,
+                "journal_mode",
+                v.as_str(),
+                |row| row.get(0)
+            ))?;
+            track_assert_eq!(mode, v.as_str(), Failed);
+        }
+        if let Some(v) = options.synchronous {
+            track_any_err!(conn.pragma_update(None, "synchronous", v.as_str()))?;
+        }
+        if let Some(v) = options.cache_size {
+            // A negative value means the size in KiB instead of the number of pages.
+            // The size is rounded up, since zero KiB would select the default cache size.
+            let kib = cmp::max(v.saturating_add(1023) / 1024, 1) as i64;
+            track_any_err!(conn.pragma_update(None, "cache_size", -kib))?;
+        }
+        track_any_err!(conn.execute(
+            "CREATE TABLE IF NOT EXISTS kvs (key BLOB PRIMARY KEY, value BLOB)",
+            [],
+        ))?;
+
+        for sql in &[PUT_SQL, GET_SQL, DELETE_SQL] {
+            track_any_err!(conn.prepare_cached(sql))?;
+        }
+
+        // The journal mode of an existing database persists, so both are queried.
+        let synchronous: i64 =
+            track_any_err!(conn.query_row("PRAGMA synchronous", [], |row| row.get(0)))?;
+        let journal_mode: String =
+            track_any_err!(conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)))?;
+        Ok(Sqlite {
+            conn,
+            synchronous,
+            wal: journal_mode == "wal",
+        })
+    }
+}
+impl KeyValueStore for Sqlite {
+    type OwnedValue = Vec<u8>;
+
+    fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+        let mut stmt = track_any_err!(self.conn.prepare_cached(PUT_SQL))?;
+        track_any_err!(stmt.execute(params![key, value]))?;
+        Ok(Existence::unknown())
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Self::OwnedValue>> {
+        let mut stmt = track_any_err!(self.conn.prepare_cached(GET_SQL))?;
+        let value = track_any_err!(stmt.query_row(params![key], |row| row.get(0)).optional())?;
+        Ok(value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+        let mut stmt = track_any_err!(self.conn.prepare_cached(DELETE_SQL))?;
+        let deleted = track_any_err!(stmt.execute(params![key]))?;
+        Ok(Existence::new(deleted > 0))
+    }
+
+    /// Every commit is already synced, except in WAL mode with `synchronous=normal`,
+    /// which only syncs the WAL on checkpoints, and with `synchronous=off`, which never syncs.
+    fn sync(&mut self) -> Result<()> {
+        track_assert!(
+            self.synchronous != 0,
+            Failed,
+            "SQLite cannot sync with `synchronous=off`"
+        );
+        if self.wal && self.synchronous == 1 {
+            track_any_err!(self
+                .conn
+                .query_row("PRAGMA wal_checkpoint(FULL)", [], |_| Ok(())))?;
+        }
+        Ok(())
+    }
+}
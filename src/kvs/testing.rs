@@ -0,0 +1,205 @@
+//! Conformance checks shared by every `KeyValueStore` implementation.
+//!
+//! A store that reports `Existence::unknown()` passes the existence checks,
+//! but any reported existence must agree with the operations issued so far.
+use crate::kvs::KeyValueStore;
+use crate::task::{CasOutcome, Existence, TxnOutcome, UpdateOp};
+use crate::Result;
+use trackable::error::Failed;
+
+/// Runs every conformance check, each against a fresh store created by `new_kvs`.
+pub fn run_conformance<T, F>(mut new_kvs: F) -> Result<()>
+where
+    T: KeyValueStore,
+    F: FnMut() -> Result<T>,
+{
+    track!(check_put_get_delete(&mut track!(new_kvs())?))?;
+    track!(check_overwrite(&mut track!(new_kvs())?))?;
+    track!(check_missing_key_delete(&mut track!(new_kvs())?))?;
+    track!(check_empty_value(&mut track!(new_kvs())?))?;
+    track!(check_large_value(&mut track!(new_kvs())?))?;
+    track!(check_binary_keys(&mut track!(new_kvs())?))?;
+    track!(check_path_like_keys(&mut track!(new_kvs())?))?;
+    track!(check_transaction(&mut track!(new_kvs())?))?;
+    track!(check_compare_and_swap(&mut track!(new_kvs())?))?;
+    track!(check_update(&mut track!(new_kvs())?))?;
+    track!(check_multi_get(&mut track!(new_kvs())?))?;
+    Ok(())
+}
+
+pub fn check_put_get_delete<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    track!(assert_value(kvs, b"foo", None))?;
+
+    let exists = track!(kvs.put(b"foo", b"bar"))?;
+    track!(assert_existence(exists, false))?;
+    track!(assert_value(kvs, b"foo", Some(b"bar")))?;
+
+    let exists = track!(kvs.delete(b"foo"))?;
+    track!(assert_existence(exists, true))?;
+    track!(assert_value(kvs, b"foo", None))?;
+    Ok(())
+}
+
+pub fn check_overwrite<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let exists = track!(kvs.put(b"foo", b"long value"))?;
+    track!(assert_existence(exists, false))?;
+
+    let exists = track!(kvs.put(b"foo", b"short"))?;
+    track!(assert_existence(exists, true))?;
+    track!(assert_value(kvs, b"foo", Some(b"short")))?;
+    Ok(())
+}
+
+pub fn check_missing_key_delete<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let exists = track!(kvs.delete(b"foo"))?;
+    track!(assert_existence(exists, false))?;
+
+    track!(kvs.put(b"foo", b"bar"))?;
+    track!(kvs.delete(b"foo"))?;
+    let exists = track!(kvs.delete(b"foo"))?;
+    track!(assert_existence(exists, false))?;
+    Ok(())
+}
+
+pub fn check_empty_value<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let exists = track!(kvs.put(b"foo", b""))?;
+    track!(assert_existence(exists, false))?;
+    track!(assert_value(kvs, b"foo", Some(b"")))?;
+    Ok(())
+}
+
+pub fn check_large_value<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let value = (0..1024 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    track!(kvs.put(b"foo", &value))?;
+    track!(assert_value(kvs, b"foo", Some(&value)))?;
+    Ok(())
+}
+
+pub fn check_binary_keys<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let keys: &[&[u8]] = &[b"\xff\xfe\x00", b"a/b", b"a%2Fb", b"\x01\x00\x01"];
+    for (i, key) in keys.iter().enumerate() {
+        let exists = track!(kvs.put(key, &[i as u8]))?;
+        track!(assert_existence(exists, false))?;
+    }
+    for (i, key) in keys.iter().enumerate() {
+        track!(assert_value(kvs, key, Some(&[i as u8])))?;
+    }
+    Ok(())
+}
+
+pub fn check_path_like_keys<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let keys: &[&[u8]] = &[
+        b".", b"..", b".tmp", b"x/..", b"/", b"/x", b"x/", b"x//y", b"%2E", b"a", b"a/b",
+    ];
+    for (i, key) in keys.iter().enumerate() {
+        let exists = track!(kvs.put(key, &[i as u8]))?;
+        track!(assert_existence(exists, false))?;
+    }
+    for (i, key) in keys.iter().enumerate() {
+        track!(assert_value(kvs, key, Some(&[i as u8])))?;
+    }
+    for key in keys {
+        let exists = track!(kvs.delete(key))?;
+        track!(assert_existence(exists, true))?;
+        track!(assert_value(kvs, key, None))?;
+    }
+    Ok(())
+}
+
+/// Checks keys longer than a file name can be.
+///
+/// This is not a part of `run_conformance`, since some stores limit the key size
+/// (e.g., LMDB accepts up to 511 bytes).
+pub fn check_long_keys<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let key = (0..511).map(|i| b'a' + (i % 26) as u8).collect::<Vec<_>>();
+    let keys = [
+        &key[..126],
+        &key[..127],
+        &key[..252],
+        &key[..253],
+        &key[..300],
+        &key[..],
+    ];
+    for (i, key) in keys.iter().enumerate() {
+        let exists = track!(kvs.put(key, &[i as u8]))?;
+        track!(assert_existence(exists, false))?;
+    }
+    for (i, key) in keys.iter().enumerate() {
+        track!(assert_value(kvs, key, Some(&[i as u8])))?;
+    }
+    Ok(())
+}
+
+/// Checks the empty key.
+///
+/// This is not a part of `run_conformance`, since some stores (e.g., LMDB) reject it.
+pub fn check_empty_key<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let exists = track!(kvs.put(b"", b"foo"))?;
+    track!(assert_existence(exists, false))?;
+    track!(assert_value(kvs, b"", Some(b"foo")))?;
+    let exists = track!(kvs.delete(b""))?;
+    track!(assert_existence(exists, true))?;
+    track!(assert_value(kvs, b"", None))?;
+    Ok(())
+}
+
+pub fn check_transaction<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    track!(kvs.put(b"foo", b"0"))?;
+    let outcome = track!(kvs.transaction(&[&b"foo"[..], &b"bar"[..]], b"1"))?;
+    track_assert_eq!(outcome, TxnOutcome::Committed { retries: 0 }, Failed);
+    track!(assert_value(kvs, b"foo", Some(b"1")))?;
+    track!(assert_value(kvs, b"bar", Some(b"1")))?;
+    Ok(())
+}
+
+pub fn check_compare_and_swap<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let outcome = track!(kvs.compare_and_swap(b"foo", None, b"0"))?;
+    track_assert_eq!(outcome, CasOutcome::Swapped, Failed);
+    let outcome = track!(kvs.compare_and_swap(b"foo", None, b"1"))?;
+    track_assert_eq!(outcome, CasOutcome::Failed, Failed);
+    let outcome = track!(kvs.compare_and_swap(b"foo", Some(&b"1"[..]), b"2"))?;
+    track_assert_eq!(outcome, CasOutcome::Failed, Failed);
+    let outcome = track!(kvs.compare_and_swap(b"foo", Some(&b"0"[..]), b"2"))?;
+    track_assert_eq!(outcome, CasOutcome::Swapped, Failed);
+    track!(assert_value(kvs, b"foo", Some(b"2")))?;
+    Ok(())
+}
+
+pub fn check_update<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    let exists = track!(kvs.update(b"counter", &UpdateOp::Add(2)))?;
+    track!(assert_existence(exists, false))?;
+    let exists = track!(kvs.update(b"counter", &UpdateOp::Add(3)))?;
+    track!(assert_existence(exists, true))?;
+    track!(assert_value(kvs, b"counter", Some(&5u64.to_be_bytes()[..])))?;
+
+    track!(kvs.update(b"log", &UpdateOp::Append(b"foo".to_vec())))?;
+    track!(kvs.update(b"log", &UpdateOp::Append(b"bar".to_vec())))?;
+    track!(assert_value(kvs, b"log", Some(b"foobar")))?;
+    Ok(())
+}
+
+pub fn check_multi_get<T: KeyValueStore>(kvs: &mut T) -> Result<()> {
+    track!(kvs.put(b"foo", b"1"))?;
+    track!(kvs.put(b"baz", b"3"))?;
+    let values = track!(kvs.multi_get(&[&b"foo"[..], &b"bar"[..], &b"baz"[..]]))?;
+    let values = values
+        .iter()
+        .map(|v| v.as_ref().map(AsRef::as_ref))
+        .collect::<Vec<Option<&[u8]>>>();
+    track_assert_eq!(values, [Some(&b"1"[..]), None, Some(&b"3"[..])], Failed);
+    Ok(())
+}
+
+fn assert_existence(actual: Existence, expected: bool) -> Result<()> {
+    if let Some(actual) = actual.exists() {
+        track_assert_eq!(actual, expected, Failed);
+    }
+    Ok(())
+}
+
+fn assert_value<T: KeyValueStore>(kvs: &mut T, key: &[u8], expected: Option<&[u8]>) -> Result<()> {
+    let actual = track!(kvs.get(key))?;
+    let actual = actual.as_ref().map(AsRef::as_ref);
+    track_assert_eq!(actual, expected, Failed; key);
+    Ok(())
+}
@@ -6,6 +6,7 @@ extern crate serde_derive;
 extern crate trackable;
 
 pub mod kvs;
+pub mod model;
 pub mod plot;
 pub mod task;
 pub mod workload;
@@ -4,15 +4,18 @@ extern crate serde_derive;
 extern crate trackable;
 
 use byte_unit::Byte;
-use clap::Parser;
+use clap::{CommandFactory, Parser, ValueEnum};
 use ekvsb::kvs::{self, KeyValueStore};
-use ekvsb::task::{Key, Seconds, Task, TaskResult, ValueSpec};
-use ekvsb::workload::{Workload, WorkloadExecutor};
+use ekvsb::task::{
+    CasOutcome, Key, RunResult, Seconds, Task, TaskResult, TxnOutcome, UpdateSpec, ValueSpec,
+};
+use ekvsb::workload::{Durability, Workload, WorkloadExecutor};
 use ekvsb::Result;
 use indicatif::ProgressBar;
 use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
+#[cfg(feature = "rocksdb")]
 use rocksdb::{self, Cache};
 use std::collections::{BTreeMap, HashMap};
 use std::io::{BufReader, BufWriter, Read, Write};
@@ -31,8 +34,8 @@ struct Opt {
 #[derive(Debug, clap::Subcommand)]
 #[allow(clippy::large_enum_variant)]
 enum Command {
-    #[clap(about = "Executes a benchmark", subcommand)]
-    Run(RunCommand),
+    #[clap(about = "Executes a benchmark")]
+    Run(RunOpt),
 
     #[clap(about = "Generates a benchmark workload", subcommand)]
     Workload(WorkloadCommand),
@@ -42,13 +45,36 @@ enum Command {
 
     #[clap(about = "Plots a benchmark result", subcommand)]
     Plot(PlotCommand),
+
+    #[clap(about = "Lists the backends compiled into this binary")]
+    Backends,
+}
+
+#[derive(Debug, clap::Args)]
+struct RunOpt {
+    #[clap(
+        long,
+        help = "Checks every result against an in-memory model (the store must be initially empty)"
+    )]
+    check_model: bool,
+
+    #[clap(
+        long,
+        default_value = "none",
+        value_parser = parse_durability,
+        help = "When to sync the store: none, flush-at-end, every-op or every-N"
+    )]
+    durability: Durability,
+
+    #[clap(subcommand)]
+    command: RunCommand,
 }
 
 #[derive(Debug, clap::Subcommand)]
 #[allow(clippy::large_enum_variant)]
 enum RunCommand {
     #[clap(name = "builtin::fs", about = "FileSystem")]
-    Fs { dir: PathBuf },
+    Fs(FsOpt),
 
     #[clap(name = "builtin::hashmap", about = "HashMap")]
     HashMap,
@@ -56,6 +82,22 @@ enum RunCommand {
     #[clap(name = "builtin::btreemap", about = "BTreeMap")]
     BTreeMap,
 
+    #[clap(
+        name = "exec",
+        about = "External program speaking the protocol described in `ekvsb::kvs::exec`"
+    )]
+    Exec {
+        #[clap(required = true, last = true)]
+        command: Vec<String>,
+    },
+
+    #[clap(
+        name = "resp",
+        about = "Redis-compatible server (RESP over TCP or a Unix socket)"
+    )]
+    Resp(RespOpt),
+
+    #[cfg(feature = "cannyls")]
     #[clap(name = "cannyls", about = "CannyLS")]
     CannyLs {
         file: PathBuf,
@@ -68,20 +110,137 @@ enum RunCommand {
 
         #[clap(long)]
         without_device: bool,
+
+        #[clap(long, default_value = "direct")]
+        key_mode: CannyLsKeyMode,
     },
 
+    #[cfg(feature = "rocksdb")]
     #[clap(name = "rocksdb", about = "RocksDB")]
     RocksDb(RocksDbOpt),
 
+    #[cfg(feature = "rocksdb")]
+    #[clap(name = "rocksdb-txn", about = "RocksDB TransactionDB")]
+    RocksDbTxn(RocksDbTxnOpt),
+
+    #[cfg(feature = "rocksdb")]
+    #[clap(name = "rocksdb-optimistic", about = "RocksDB OptimisticTransactionDB")]
+    RocksDbOptimistic(RocksDbTxnOpt),
+
+    #[cfg(feature = "sled")]
     #[clap(name = "sled", about = "Sled")]
-    Sled { dir: PathBuf },
+    Sled(SledOpt),
+
+    #[cfg(feature = "redb")]
+    #[clap(name = "redb", about = "redb")]
+    Redb(RedbOpt),
+
+    #[cfg(feature = "sqlite")]
+    #[clap(name = "sqlite", about = "SQLite")]
+    Sqlite(SqliteOpt),
+
+    #[cfg(feature = "lmdb")]
+    #[clap(name = "lmdb", about = "LMDB")]
+    Lmdb(LmdbOpt),
+
+    #[cfg(feature = "fjall")]
+    #[clap(name = "fjall", about = "fjall")]
+    Fjall(FjallOpt),
+}
+
+#[derive(Debug, clap::Args)]
+struct RespOpt {
+    #[clap(help = "HOST:PORT of a TCP server, or `unix:PATH` of a Unix socket")]
+    address: String,
+
+    #[clap(
+        long,
+        help = "Issues `SET key value GET` (Redis 6.2 or later) so that puts report existence"
+    )]
+    set_get: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct FsOpt {
+    dir: PathBuf,
+
+    #[clap(long, help = "Reports accurate existence of keys by probing files")]
+    probe_existence: bool,
+
+    #[clap(long, default_value = "in-place")]
+    write_strategy: WriteStrategy,
+
+    #[clap(
+        long,
+        default_value = "3",
+        help = "The number of directory levels (0 means a flat layout)"
+    )]
+    shard_depth: usize,
+
+    #[clap(long, default_value = "256")]
+    shard_fan_out: u64,
+
+    #[clap(long, default_value = "sip13")]
+    shard_hash: ShardHash,
+
+    #[clap(long, default_value = "percent")]
+    key_encoding: FsKeyEncoding,
+
+    #[clap(long, help = "Removes directories that become empty on DELETE")]
+    cleanup_empty_dirs: bool,
 }
 
+#[cfg(feature = "rocksdb")]
 #[derive(Debug, clap::Args)]
 struct RocksDbOpt {
     dir: PathBuf,
 
+    #[clap(
+        long,
+        help = "Loads a native RocksDB options file (the other flags take precedence)"
+    )]
+    options_file: Option<PathBuf>,
+
     #[clap(long)]
+    preset: Option<RocksDbPreset>,
+
+    #[clap(
+        long,
+        help = "Enables RocksDB statistics and attaches them to the run result"
+    )]
+    statistics: bool,
+
+    #[clap(long, help = "Also collects RocksDB statistics every N operations")]
+    statistics_interval: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Reports accurate existence of keys by looking them up before writes"
+    )]
+    probe_existence: bool,
+
+    #[clap(
+        long = "column-family",
+        value_name = "NAME=PREFIX",
+        value_parser = parse_column_family,
+        help = "Stores keys starting with PREFIX in the column family NAME (repeatable)"
+    )]
+    column_family: Vec<(String, String)>,
+
+    #[clap(
+        long = "cf-option",
+        value_name = "NAME:KEY=VALUE",
+        value_parser = parse_cf_option,
+        help = "Sets an option of the column family NAME (KEY: preset, write_buffer_size, \
+                max_write_buffer_number, target_file_size_base, optimize_for_point_lookup, \
+                disable_auto_compactions or bloom_filter_bits_per_key)"
+    )]
+    cf_option: Vec<(String, String, String)>,
+
+    #[clap(
+        long,
+        conflicts_with_all = ["options_file", "preset", "statistics", "statistics_interval"]
+    )]
     force_default: bool,
 
     #[clap(long)]
@@ -198,113 +357,291 @@ struct RocksDbOpt {
 
     #[clap(long)]
     block_opt_index_type: Option<BlockBasedIndexType>,
+
+    #[clap(flatten)]
+    io: RocksDbIoOpt,
+}
+
+#[cfg(feature = "rocksdb")]
+#[derive(Debug, clap::Args)]
+struct RocksDbIoOpt {
+    #[clap(long)]
+    write_opt_sync: bool,
+
+    #[clap(long)]
+    write_opt_disable_wal: bool,
+
+    #[clap(long)]
+    write_opt_no_slowdown: bool,
+
+    #[clap(long)]
+    write_opt_low_pri: bool,
+
+    #[clap(long)]
+    read_opt_disable_fill_cache: bool,
+
+    #[clap(long)]
+    read_opt_disable_verify_checksums: bool,
+
+    #[clap(long)]
+    read_opt_readahead_size: Option<usize>,
+}
+
+#[cfg(feature = "rocksdb")]
+#[derive(Debug, clap::Args)]
+struct RocksDbTxnOpt {
+    dir: PathBuf,
+
+    #[clap(long, help = "Loads a native RocksDB options file")]
+    options_file: Option<PathBuf>,
+
+    #[clap(long)]
+    preset: Option<RocksDbPreset>,
+
+    #[clap(
+        long,
+        default_value = "10",
+        help = "Retries of a transaction on commit conflicts"
+    )]
+    max_retries: usize,
+
+    #[clap(flatten)]
+    io: RocksDbIoOpt,
+}
+
+#[cfg(feature = "sled")]
+#[derive(Debug, clap::Args)]
+struct SledOpt {
+    dir: PathBuf,
+
+    #[clap(long, value_parser = parse_size_u64)]
+    cache_capacity: Option<u64>,
+
+    #[clap(long, conflicts_with = "disable_flush")]
+    flush_every_ms: Option<u64>,
+
+    #[clap(long, help = "Disables the periodic background flush")]
+    disable_flush: bool,
+
+    #[clap(long)]
+    use_compression: bool,
+
+    #[clap(long, requires = "use_compression")]
+    compression_factor: Option<i32>,
+
+    #[clap(long, value_parser = parse_size)]
+    segment_size: Option<usize>,
+
+    #[clap(long)]
+    mode: Option<SledMode>,
+}
+
+#[cfg(feature = "redb")]
+#[derive(Debug, clap::Args)]
+struct RedbOpt {
+    file: PathBuf,
+
+    #[clap(long, value_parser = parse_size)]
+    cache_size: Option<usize>,
+
+    #[clap(
+        long,
+        default_value = "immediate",
+        help = "Durability of the write transaction committed for each operation"
+    )]
+    commit_durability: RedbDurability,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, clap::Args)]
+struct SqliteOpt {
+    file: PathBuf,
+
+    #[clap(long)]
+    journal_mode: Option<SqliteJournalMode>,
+
+    #[clap(long)]
+    synchronous: Option<SqliteSynchronous>,
+
+    #[clap(long, value_parser = parse_size)]
+    page_size: Option<usize>,
+
+    #[clap(long, value_parser = parse_size_u64)]
+    cache_size: Option<u64>,
+}
+
+#[cfg(feature = "lmdb")]
+#[derive(Debug, clap::Args)]
+struct LmdbOpt {
+    dir: PathBuf,
+
+    #[clap(long, default_value = "1GiB", value_parser = parse_size)]
+    map_size: usize,
+
+    #[clap(long)]
+    no_sync: bool,
+
+    #[clap(long)]
+    no_meta_sync: bool,
+
+    #[clap(long)]
+    write_map: bool,
+
+    #[clap(
+        long,
+        default_value = "1",
+        help = "Number of operations grouped into one write transaction"
+    )]
+    ops_per_txn: usize,
+
+    #[clap(
+        long,
+        help = "Reports accurate existence of keys by trying non-overwriting puts first"
+    )]
+    probe_existence: bool,
+}
+
+#[cfg(feature = "fjall")]
+#[derive(Debug, clap::Args)]
+struct FjallOpt {
+    dir: PathBuf,
+
+    #[clap(long, value_parser = parse_size_u64)]
+    cache_size: Option<u64>,
+
+    #[clap(long, value_parser = parse_size)]
+    max_memtable_size: Option<usize>,
+
+    #[clap(long, value_parser = parse_size_u64)]
+    max_write_buffer_size: Option<u64>,
+
+    #[clap(long)]
+    compaction_strategy: Option<FjallCompactionStrategy>,
+
+    #[clap(long, help = "Interval of the background journal fsync")]
+    fsync_ms: Option<u16>,
+
+    #[clap(long, help = "Persists the journal in this mode after every write")]
+    persist_mode: Option<FjallPersistMode>,
 }
 
 #[derive(Debug, clap::Subcommand)]
 enum WorkloadCommand {
     #[clap(about = "PUT workload")]
     Put {
-        #[clap(long, default_value = "1000")]
-        count: usize,
-
-        #[clap(long)]
-        population_size: Option<usize>,
-
-        #[clap(long, default_value = "10")]
-        key_size: usize,
+        #[clap(flatten)]
+        common: WorkloadOpt,
 
         #[clap(long, default_value = "1KiB", value_parser = parse_size)]
         value_size: usize,
-
-        #[clap(long)]
-        seed: Option<String>,
-
-        #[clap(long)]
-        shuffle: Option<String>,
     },
 
     #[clap(about = "GET workload")]
     Get {
-        #[clap(long, default_value = "1000")]
-        count: usize,
-
-        #[clap(long)]
-        population_size: Option<usize>,
-
-        #[clap(long, default_value = "10")]
-        key_size: usize,
+        #[clap(flatten)]
+        common: WorkloadOpt,
+    },
 
-        #[clap(long)]
-        seed: Option<String>,
+    #[clap(about = "Multi-get (batched GET) workload")]
+    MultiGet {
+        #[clap(flatten)]
+        common: WorkloadOpt,
 
-        #[clap(long)]
-        shuffle: Option<String>,
+        #[clap(long, default_value = "8")]
+        keys_per_call: usize,
     },
 
     #[clap(about = "DELETE workload")]
     Delete {
-        #[clap(long, default_value = "1000")]
-        count: usize,
+        #[clap(flatten)]
+        common: WorkloadOpt,
+    },
 
-        #[clap(long)]
-        population_size: Option<usize>,
+    #[clap(name = "cas", about = "Compare-and-swap workload")]
+    CompareAndSwap {
+        #[clap(flatten)]
+        common: WorkloadOpt,
 
-        #[clap(long, default_value = "10")]
-        key_size: usize,
+        #[clap(long, default_value = "1KiB", value_parser = parse_size)]
+        value_size: usize,
 
-        #[clap(long)]
-        seed: Option<String>,
+        #[clap(
+            long,
+            help = "Swaps only if keys do not exist (instead of their current values)"
+        )]
+        expect_absent: bool,
+    },
 
-        #[clap(long)]
-        shuffle: Option<String>,
+    #[clap(about = "Read-modify-write workload (adds to counters or appends to values)")]
+    Update {
+        #[clap(flatten)]
+        common: WorkloadOpt,
+
+        #[clap(
+            long,
+            default_value = "1",
+            help = "Amount added to big-endian u64 counters"
+        )]
+        add: u64,
+
+        #[clap(
+            long,
+            value_parser = parse_size,
+            conflicts_with = "add",
+            help = "Appends random bytes of this size instead of adding to counters"
+        )]
+        append_size: Option<usize>,
+    },
+
+    #[clap(about = "Multi-key read-modify-write transaction workload")]
+    Transaction {
+        #[clap(flatten)]
+        common: WorkloadOpt,
+
+        #[clap(long, default_value = "2")]
+        keys_per_transaction: usize,
+
+        #[clap(long, default_value = "1KiB", value_parser = parse_size)]
+        value_size: usize,
     },
 }
 impl WorkloadCommand {
-    fn count(&self) -> usize {
+    fn common(&self) -> &WorkloadOpt {
         match self {
-            WorkloadCommand::Put { count, .. }
-            | WorkloadCommand::Get { count, .. }
-            | WorkloadCommand::Delete { count, .. } => *count,
+            WorkloadCommand::Put { common, .. }
+            | WorkloadCommand::Get { common }
+            | WorkloadCommand::Delete { common }
+            | WorkloadCommand::Transaction { common, .. }
+            | WorkloadCommand::Update { common, .. }
+            | WorkloadCommand::MultiGet { common, .. }
+            | WorkloadCommand::CompareAndSwap { common, .. } => common,
         }
     }
+}
 
-    fn population_size(&self) -> Option<usize> {
-        match self {
-            WorkloadCommand::Put {
-                population_size, ..
-            }
-            | WorkloadCommand::Get {
-                population_size, ..
-            }
-            | WorkloadCommand::Delete {
-                population_size, ..
-            } => *population_size,
-        }
-    }
+/// Options shared by every workload type.
+#[derive(Debug, clap::Args)]
+struct WorkloadOpt {
+    #[clap(long, default_value = "1000")]
+    count: usize,
 
-    fn key_size(&self) -> usize {
-        match self {
-            WorkloadCommand::Put { key_size, .. }
-            | WorkloadCommand::Get { key_size, .. }
-            | WorkloadCommand::Delete { key_size, .. } => *key_size,
-        }
-    }
+    #[clap(long)]
+    population_size: Option<usize>,
 
-    fn seed(&self) -> Option<&str> {
-        match self {
-            WorkloadCommand::Put { seed, .. }
-            | WorkloadCommand::Get { seed, .. }
-            | WorkloadCommand::Delete { seed, .. } => seed.as_ref().map(String::as_str),
-        }
-    }
+    #[clap(long, default_value = "10", value_parser = parse_key_size)]
+    key_size: KeySize,
 
-    fn shuffle(&self) -> Option<&str> {
-        match self {
-            WorkloadCommand::Put { shuffle, .. }
-            | WorkloadCommand::Get { shuffle, .. }
-            | WorkloadCommand::Delete { shuffle, .. } => shuffle.as_ref().map(String::as_str),
-        }
-    }
+    #[clap(long, default_value = "alnum", value_delimiter = ',')]
+    key_format: Vec<KeyFormat>,
+
+    #[clap(long)]
+    key_prefix: Option<String>,
+
+    #[clap(long)]
+    seed: Option<String>,
+
+    #[clap(long)]
+    shuffle: Option<String>,
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -377,6 +714,120 @@ impl PlotCommand {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct KeySize {
+    min: usize,
+    max: usize,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum KeyFormat {
+    #[clap(help = "Random alphanumeric characters (length: --key-size)")]
+    Alnum,
+    #[clap(help = "Random bytes (length: --key-size)")]
+    Bytes,
+    #[clap(help = "Big-endian sequential 64-bit integer")]
+    U64,
+    #[clap(help = "Random UUID (version 4) in hyphenated form")]
+    Uuid,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum WriteStrategy {
+    #[clap(help = "Truncates and overwrites files in place")]
+    InPlace,
+    #[clap(help = "Writes to temporary files and renames them")]
+    Rename,
+    #[clap(help = "Same as `rename`, but also fsyncs files and their parent directories")]
+    DurableRename,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum ShardHash {
+    Sip13,
+    Sip24,
+    Fnv1a,
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum FsKeyEncoding {
+    Percent,
+    Hex,
+}
+
+#[cfg(feature = "cannyls")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum CannyLsKeyMode {
+    #[clap(help = "Uses keys as LumpIds as is (keys must be at most 16 bytes)")]
+    Direct,
+    #[clap(help = "Uses 128-bit hashes of keys as LumpIds")]
+    Hash,
+    #[clap(help = "Same as `hash`, but detects collisions by storing keys inside lumps")]
+    CheckedHash,
+}
+
+#[cfg(feature = "sled")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum SledMode {
+    LowSpace,
+    HighThroughput,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum SqliteJournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum SqliteSynchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+#[cfg(feature = "fjall")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum FjallCompactionStrategy {
+    Leveled,
+    SizeTiered,
+}
+
+#[cfg(feature = "fjall")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum FjallPersistMode {
+    Buffer,
+    SyncData,
+    SyncAll,
+}
+
+#[cfg(feature = "redb")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum RedbDurability {
+    #[clap(help = "Commits are not persisted until a later durable commit")]
+    None,
+    Eventual,
+    Immediate,
+    Paranoid,
+}
+
+#[cfg(feature = "rocksdb")]
+#[derive(Debug, Clone, clap::ValueEnum)]
+enum RocksDbPreset {
+    PointLookup,
+    WriteHeavy,
+    LowMemory,
+    BulkLoad,
+}
+
+#[cfg(feature = "rocksdb")]
 #[derive(Debug, Clone, clap::ValueEnum)]
 enum CompactionStyle {
     Level,
@@ -384,6 +835,7 @@ enum CompactionStyle {
     Fifo,
 }
 
+#[cfg(feature = "rocksdb")]
 #[derive(Debug, Clone, clap::ValueEnum)]
 #[allow(clippy::enum_variant_names)]
 enum BlockBasedIndexType {
@@ -396,8 +848,8 @@ fn main() -> trackable::result::MainResult {
     let opt = Opt::parse();
 
     match opt.command {
-        Command::Run(ref command) => {
-            track!(handle_run_subcommand(&opt, command))?;
+        Command::Run(ref run_opt) => {
+            track!(handle_run_subcommand(&opt, run_opt))?;
         }
         Command::Workload(ref command) => {
             track!(handle_workload_subcommand(command))?;
@@ -408,11 +860,14 @@ fn main() -> trackable::result::MainResult {
         Command::Plot(ref command) => {
             track!(handle_plot_subcommand(command))?;
         }
+        Command::Backends => {
+            track!(handle_backends_subcommand())?;
+        }
     }
     Ok(())
 }
 
-fn handle_run_subcommand(opt: &Opt, command: &RunCommand) -> Result<()> {
+fn handle_run_subcommand(opt: &Opt, run_opt: &RunOpt) -> Result<()> {
     let _reserved_memory: Vec<u8> = vec![1; opt.memory_load];
 
     let workload: Workload = track_any_err!(
@@ -420,56 +875,167 @@ fn handle_run_subcommand(opt: &Opt, command: &RunCommand) -> Result<()> {
         "Malformed input workload JSON"
     )?;
 
-    match command {
-        RunCommand::Fs { dir } => {
-            let kvs = track!(kvs::FileSystemKvs::new(dir))?;
-            track!(execute(kvs, workload))?;
+    match &run_opt.command {
+        RunCommand::Fs(opt) => {
+            let kvs = track!(make_fs_kvs(opt, run_opt.durability))?;
+            track!(execute(kvs, workload, run_opt))?;
         }
         RunCommand::HashMap => {
             let kvs = HashMap::new();
-            track!(execute(kvs, workload))?;
+            track!(execute(kvs, workload, run_opt))?;
         }
         RunCommand::BTreeMap => {
             let kvs = BTreeMap::new();
-            track!(execute(kvs, workload))?;
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        RunCommand::Exec { command } => {
+            let mut child = std::process::Command::new(&command[0]);
+            child.args(&command[1..]);
+            let kvs = track!(kvs::ExecKvs::new(child))?;
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        RunCommand::Resp(opt) => {
+            let mut kvs = track!(connect_resp(&opt.address))?;
+            if opt.set_get {
+                kvs.enable_set_get();
+            }
+            track!(execute(kvs, workload, run_opt))?;
         }
+        #[cfg(feature = "cannyls")]
         RunCommand::CannyLs {
             file,
             capacity,
             journal_sync_interval,
             without_device,
+            key_mode,
         } => {
+            let key_mode = match key_mode {
+                CannyLsKeyMode::Direct => kvs::CannyLsKeyMode::Direct,
+                CannyLsKeyMode::Hash => kvs::CannyLsKeyMode::Hash,
+                CannyLsKeyMode::CheckedHash => kvs::CannyLsKeyMode::CheckedHash,
+            };
             let options = kvs::CannyLsOptions {
                 capacity: *capacity,
                 journal_sync_interval: *journal_sync_interval,
+                key_mode,
             };
             if *without_device {
                 let kvs = track!(kvs::CannyLsStorage::new(file, &options))?;
-                track!(execute(kvs, workload))?;
+                track!(execute(kvs, workload, run_opt))?;
             } else {
                 let kvs = track!(kvs::CannyLsDevice::new(file, &options))?;
-                track!(execute(kvs, workload))?;
+                track!(execute(kvs, workload, run_opt))?;
             }
         }
+        #[cfg(feature = "rocksdb")]
         RunCommand::RocksDb(opt) => {
-            let options = track!(make_rocksdb_options(opt))?;
-            let kvs = track!(kvs::RocksDb::with_options(&opt.dir, options))?;
-            track!(execute(kvs, workload))?;
+            let (options, block_opts) = track!(make_rocksdb_options(opt))?;
+            let column_families = track!(make_rocksdb_column_families(opt, &options, &block_opts))?;
+            let mut kvs = track!(kvs::RocksDb::with_column_families(
+                &opt.dir,
+                options,
+                column_families
+            ))?;
+            kvs.set_write_options(make_rocksdb_write_options(&opt.io));
+            kvs.set_read_options(make_rocksdb_read_options(&opt.io));
+            if opt.io.write_opt_disable_wal {
+                kvs.disable_wal();
+            }
+            if let Some(v) = opt.statistics_interval {
+                track_assert!(v > 0, Failed; v);
+                kvs.set_statistics_interval(v);
+            }
+            if opt.probe_existence {
+                kvs.enable_existence_probe();
+            }
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        #[cfg(feature = "rocksdb")]
+        RunCommand::RocksDbTxn(opt) => {
+            track_assert_eq!(
+                run_opt.durability,
+                Durability::None,
+                Failed,
+                "rocksdb-txn cannot sync (use `--write-opt-sync` instead)"
+            );
+            let options = track!(make_rocksdb_txn_options(opt))?;
+            let mut kvs = track!(kvs::RocksDbTxn::with_options(&opt.dir, options))?;
+            kvs.set_max_retries(opt.max_retries);
+            kvs.set_write_options(make_rocksdb_write_options(&opt.io));
+            kvs.set_read_options(make_rocksdb_read_options(&opt.io));
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        #[cfg(feature = "rocksdb")]
+        RunCommand::RocksDbOptimistic(opt) => {
+            let options = track!(make_rocksdb_txn_options(opt))?;
+            let mut kvs = track!(kvs::RocksDbOptimistic::with_options(&opt.dir, options))?;
+            kvs.set_max_retries(opt.max_retries);
+            kvs.set_write_options(make_rocksdb_write_options(&opt.io));
+            kvs.set_read_options(make_rocksdb_read_options(&opt.io));
+            if opt.io.write_opt_disable_wal {
+                kvs.disable_wal();
+            }
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        #[cfg(feature = "sled")]
+        RunCommand::Sled(opt) => {
+            let config = make_sled_config(opt);
+            let kvs = track!(kvs::SledTree::with_config(&opt.dir, config))?;
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        #[cfg(feature = "redb")]
+        RunCommand::Redb(opt) => {
+            let kvs = track!(make_redb(opt))?;
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        #[cfg(feature = "sqlite")]
+        RunCommand::Sqlite(opt) => {
+            if matches!(opt.synchronous, Some(SqliteSynchronous::Off)) {
+                track_assert_eq!(
+                    run_opt.durability,
+                    Durability::None,
+                    Failed,
+                    "SQLite cannot sync with `--synchronous off`"
+                );
+            }
+            let options = track!(make_sqlite_options(opt))?;
+            let kvs = track!(kvs::Sqlite::with_options(&opt.file, options))?;
+            track!(execute(kvs, workload, run_opt))?;
         }
-        RunCommand::Sled { dir } => {
-            let kvs = track!(kvs::SledTree::new(dir))?;
-            track!(execute(kvs, workload))?;
+        #[cfg(feature = "lmdb")]
+        RunCommand::Lmdb(opt) => {
+            let options = kvs::LmdbOptions {
+                map_size: opt.map_size,
+                no_sync: opt.no_sync,
+                no_meta_sync: opt.no_meta_sync,
+                write_map: opt.write_map,
+                ops_per_txn: opt.ops_per_txn,
+            };
+            let mut kvs = track!(kvs::Lmdb::with_options(&opt.dir, options))?;
+            if opt.probe_existence {
+                kvs.enable_existence_probe();
+            }
+            track!(execute(kvs, workload, run_opt))?;
+        }
+        #[cfg(feature = "fjall")]
+        RunCommand::Fjall(opt) => {
+            let kvs = track!(make_fjall(opt))?;
+            track!(execute(kvs, workload, run_opt))?;
         }
     }
     Ok(())
 }
 
-fn execute<T: KeyValueStore>(kvs: T, workload: Workload) -> Result<()> {
+fn execute<T: KeyValueStore>(kvs: T, workload: Workload, run_opt: &RunOpt) -> Result<()> {
     let pb = ProgressBar::new(workload.len() as u64);
-    let executor = WorkloadExecutor::new(kvs, workload);
+    let mut executor = WorkloadExecutor::new(kvs, workload);
+    if run_opt.check_model {
+        executor.enable_model_check();
+    }
+    executor.set_durability(run_opt.durability);
 
-    println!("[");
-    for (i, result) in executor.enumerate() {
+    println!("{{\"results\": [");
+    for (i, result) in (&mut executor).enumerate() {
         if i != 0 {
             print!(",\n  ");
         } else {
@@ -478,7 +1044,11 @@ fn execute<T: KeyValueStore>(kvs: T, workload: Workload) -> Result<()> {
         pb.inc(1);
         track_any_err!(serde_json::to_writer(stdout(), &result))?;
     }
-    println!("\n]");
+    println!("\n],");
+    let metadata = track!(executor.kvs_mut().metadata())?;
+    print!("\"metadata\": ");
+    track_any_err!(serde_json::to_writer(stdout(), &metadata))?;
+    println!("}}");
     Ok(())
 }
 
@@ -494,6 +1064,50 @@ fn handle_workload_subcommand(command: &WorkloadCommand) -> Result<()> {
         WorkloadCommand::Delete { .. } => {
             track!(generate_tasks(command, |key| Task::Delete { key }))?
         }
+        WorkloadCommand::CompareAndSwap {
+            value_size,
+            expect_absent,
+            ..
+        } => track!(generate_tasks(command, |key| Task::CompareAndSwap {
+            key,
+            value: ValueSpec::Random { size: *value_size },
+            expect_absent: *expect_absent,
+        }))?,
+        WorkloadCommand::Update {
+            add, append_size, ..
+        } => track!(generate_tasks(command, |key| Task::Update {
+            key,
+            op: match append_size {
+                Some(size) => UpdateSpec::Append(ValueSpec::Random { size: *size }),
+                None => UpdateSpec::Add(*add),
+            },
+        }))?,
+        WorkloadCommand::MultiGet {
+            common,
+            keys_per_call,
+        } => {
+            track_assert!(*keys_per_call > 0, Failed; keys_per_call);
+            let keys = track!(generate_keys(common, common.count * keys_per_call))?;
+            keys.chunks(*keys_per_call)
+                .map(|keys| Task::MultiGet {
+                    keys: keys.to_vec(),
+                })
+                .collect()
+        }
+        WorkloadCommand::Transaction {
+            common,
+            keys_per_transaction,
+            value_size,
+        } => {
+            track_assert!(*keys_per_transaction > 0, Failed; keys_per_transaction);
+            let keys = track!(generate_keys(common, common.count * keys_per_transaction))?;
+            keys.chunks(*keys_per_transaction)
+                .map(|keys| Task::Transaction {
+                    keys: keys.to_vec(),
+                    value: ValueSpec::Random { size: *value_size },
+                })
+                .collect()
+        }
     };
     track_any_err!(serde_json::to_writer(stdout(), &tasks))?;
     Ok(())
@@ -503,10 +1117,15 @@ fn generate_tasks<F>(command: &WorkloadCommand, f: F) -> Result<Vec<Task>>
 where
     F: Fn(Key) -> Task,
 {
-    let count = command.count();
-    let key_size = command.key_size();
-    let seed = command.seed();
-    let population_size = command.population_size().unwrap_or(count);
+    let common = command.common();
+    let keys = track!(generate_keys(common, common.count))?;
+    Ok(keys.into_iter().map(f).collect())
+}
+
+fn generate_keys(opt: &WorkloadOpt, count: usize) -> Result<Vec<Key>> {
+    let key_size = opt.key_size;
+    let seed = opt.seed.as_ref();
+    let population_size = opt.population_size.unwrap_or(count);
     track_assert!(count <= population_size, Failed; count, population_size);
 
     let mut rng = if let Some(seed) = seed {
@@ -520,17 +1139,16 @@ where
         StdRng::from_seed(rand::thread_rng().gen())
     };
 
-    const CHARS: &[u8; 62] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
-    let mut tasks = Vec::new();
-    let mut key = vec![0u8; key_size];
-    for _ in 0..population_size {
-        for b in &mut key {
-            *b = *CHARS.choose(&mut rng).expect("never fails");
+    let mut keys = Vec::new();
+    for i in 0..population_size {
+        let mut key = opt.key_prefix.as_deref().unwrap_or("").as_bytes().to_vec();
+        for format in &opt.key_format {
+            generate_key_component(&mut key, format, i as u64, key_size, &mut rng);
         }
-        tasks.push(f(track!(Key::from_utf8(key.clone()))?));
+        keys.push(Key::from_bytes(key));
     }
 
-    if let Some(seed) = command.shuffle() {
+    if let Some(seed) = &opt.shuffle {
         track_assert!(seed.len() <= 32, Failed; seed.len());
         let mut seed_bytes = [0; 32];
         for (i, b) in seed.bytes().enumerate() {
@@ -538,33 +1156,88 @@ where
         }
 
         let mut shuffle_rng = StdRng::from_seed(seed_bytes);
-        tasks.shuffle(&mut shuffle_rng);
+        keys.shuffle(&mut shuffle_rng);
     }
 
-    tasks.truncate(count);
+    keys.truncate(count);
 
-    Ok(tasks)
+    Ok(keys)
+}
+
+fn generate_key_component<R: Rng>(
+    key: &mut Vec<u8>,
+    format: &KeyFormat,
+    seqno: u64,
+    size: KeySize,
+    rng: &mut R,
+) {
+    const CHARS: &[u8; 62] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    match format {
+        KeyFormat::Alnum => {
+            let size = rng.gen_range(size.min..=size.max);
+            key.extend((0..size).map(|_| *CHARS.choose(rng).expect("never fails")));
+        }
+        KeyFormat::Bytes => {
+            let size = rng.gen_range(size.min..=size.max);
+            key.extend((0..size).map(|_| rng.gen::<u8>()));
+        }
+        KeyFormat::U64 => {
+            key.extend_from_slice(&seqno.to_be_bytes());
+        }
+        KeyFormat::Uuid => {
+            let mut b: [u8; 16] = rng.gen();
+            b[6] = (b[6] & 0x0f) | 0x40;
+            b[8] = (b[8] & 0x3f) | 0x80;
+            let uuid = format!(
+                "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+                b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+                b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]
+            );
+            key.extend_from_slice(uuid.as_bytes());
+        }
+    }
 }
 
 fn handle_summary_subcommand() -> Result<()> {
-    let results: Vec<TaskResult> = track_any_err!(
+    let RunResult {
+        results,
+        mut metadata,
+    } = track_any_err!(
         serde_json::from_reader(stdin()),
         "Malformed run result JSON"
     )?;
 
     let errors = results.iter().filter(|r| r.error.is_some()).count();
-    let oks = results.len() - errors;
+    let transactions = Transactions::new(&results);
+    let conflicts = transactions.as_ref().map_or(0, |t| t.conflicts);
+    let oks = results.len() - errors - conflicts;
     let elapsed = results.iter().map(|r| r.elapsed.as_f64()).sum();
     let ops = results.len() as f64 / elapsed;
+    let divergences = results.iter().filter(|r| r.divergence.is_some()).count();
+    let probe_elapsed = results
+        .iter()
+        .filter_map(|r| r.probe_elapsed.map(Seconds::as_f64))
+        .fold(None, |acc, x| Some(acc.unwrap_or(0.0) + x));
+    let sync_elapsed = results
+        .iter()
+        .filter_map(|r| r.sync_elapsed.map(Seconds::as_f64))
+        .fold(None, |acc, x| Some(acc.unwrap_or(0.0) + x));
     let existence = Existence::new(&results);
     let latency = Latency::new(&results);
     let summary = Summary {
         oks,
         errors,
+        transactions,
+        compare_and_swaps: CompareAndSwaps::new(&results),
+        multi_gets: MultiGets::new(&results),
+        divergences,
         existence,
         elapsed,
+        probe_elapsed,
+        sync_elapsed,
         ops,
         latency,
+        rocksdb_statistics: metadata.remove("rocksdb_statistics"),
     };
     track_any_err!(serde_json::to_writer_pretty(stdout(), &summary))?;
     println!();
@@ -576,10 +1249,103 @@ fn handle_summary_subcommand() -> Result<()> {
 struct Summary {
     oks: usize,
     errors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transactions: Option<Transactions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compare_and_swaps: Option<CompareAndSwaps>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    multi_gets: Option<MultiGets>,
+    divergences: usize,
     existence: Existence,
     elapsed: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probe_elapsed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync_elapsed: Option<f64>,
     ops: f64,
     latency: Latency,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rocksdb_statistics: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct Transactions {
+    committed: usize,
+    conflicts: usize,
+    retries: usize,
+}
+impl Transactions {
+    fn new(results: &[TaskResult]) -> Option<Self> {
+        let mut transactions = None;
+        for outcome in results.iter().filter_map(|r| r.transaction) {
+            let t = transactions.get_or_insert(Transactions {
+                committed: 0,
+                conflicts: 0,
+                retries: 0,
+            });
+            match outcome {
+                TxnOutcome::Committed { .. } => t.committed += 1,
+                TxnOutcome::Conflicted { .. } => t.conflicts += 1,
+            }
+            t.retries += outcome.retries();
+        }
+        transactions
+    }
+}
+
+#[derive(Serialize)]
+struct CompareAndSwaps {
+    swapped: usize,
+    failed: usize,
+}
+impl CompareAndSwaps {
+    fn new(results: &[TaskResult]) -> Option<Self> {
+        let mut cas = None;
+        for outcome in results.iter().filter_map(|r| r.compare_and_swap) {
+            let c = cas.get_or_insert(CompareAndSwaps {
+                swapped: 0,
+                failed: 0,
+            });
+            match outcome {
+                CasOutcome::Swapped => c.swapped += 1,
+                CasOutcome::Failed => c.failed += 1,
+            }
+        }
+        cas
+    }
+}
+
+/// Multi-get counts; `ops` and `latency` of `Summary` are per call, `keys_per_sec` is per key.
+#[derive(Serialize)]
+struct MultiGets {
+    calls: usize,
+    keys: usize,
+    found: usize,
+    keys_per_sec: f64,
+}
+impl MultiGets {
+    fn new(results: &[TaskResult]) -> Option<Self> {
+        let mut multi_gets = None;
+        let mut elapsed = 0.0;
+        for r in results {
+            if let Some(outcome) = r.multi_get {
+                let m = multi_gets.get_or_insert(MultiGets {
+                    calls: 0,
+                    keys: 0,
+                    found: 0,
+                    keys_per_sec: 0.0,
+                });
+                m.calls += 1;
+                m.keys += outcome.keys;
+                m.found += outcome.found;
+                elapsed += r.elapsed.as_f64();
+            }
+        }
+        if let Some(m) = multi_gets.as_mut() {
+            m.keys_per_sec = m.keys as f64 / elapsed;
+        }
+        multi_gets
+    }
 }
 
 #[derive(Serialize)]
@@ -636,6 +1402,17 @@ impl Latency {
     }
 }
 
+fn handle_backends_subcommand() -> Result<()> {
+    // The `run` subcommands of backends disabled by cargo features are not compiled in.
+    let command = Opt::command();
+    let run = track_assert_some!(command.find_subcommand("run"), Failed);
+    for backend in run.get_subcommands() {
+        let about = backend.get_about().map(ToString::to_string);
+        println!("{}\t{}", backend.get_name(), about.unwrap_or_default());
+    }
+    Ok(())
+}
+
 fn handle_plot_subcommand(command: &PlotCommand) -> Result<()> {
     let mut options = ekvsb::plot::PlotOptions::new();
 
@@ -662,7 +1439,7 @@ fn handle_plot_subcommand(command: &PlotCommand) -> Result<()> {
         options.y_max = Some(y_max);
     }
 
-    let results: Vec<TaskResult> = track_any_err!(
+    let RunResult { results, .. } = track_any_err!(
         serde_json::from_reader(stdin()),
         "Malformed run result JSON"
     )?;
@@ -676,6 +1453,52 @@ fn parse_size(s: &str) -> Result<usize> {
     Ok(size.get_bytes() as usize)
 }
 
+fn parse_key_size(s: &str) -> Result<KeySize> {
+    let (min, max) = if let Some((min, max)) = s.split_once('-') {
+        (min, max)
+    } else {
+        (s, s)
+    };
+    let min = track_any_err!(min.parse(), "Parse Error: {:?}", s)?;
+    let max = track_any_err!(max.parse(), "Parse Error: {:?}", s)?;
+    track_assert!(min <= max, Failed; min, max);
+    Ok(KeySize { min, max })
+}
+
+fn parse_durability(s: &str) -> Result<Durability> {
+    match s {
+        "none" => Ok(Durability::None),
+        "flush-at-end" => Ok(Durability::FlushAtEnd),
+        "every-op" => Ok(Durability::Every(1)),
+        _ => {
+            let n = track_assert_some!(s.strip_prefix("every-"), Failed; s);
+            let n: usize = track_any_err!(n.parse(), "Parse Error: {:?}", s)?;
+            track_assert!(n > 0, Failed; s);
+            Ok(Durability::Every(n))
+        }
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+fn parse_column_family(s: &str) -> Result<(String, String)> {
+    let (name, prefix) = track_assert_some!(s.split_once('='), Failed; s);
+    Ok((name.to_owned(), prefix.to_owned()))
+}
+
+#[cfg(feature = "rocksdb")]
+fn parse_cf_option(s: &str) -> Result<(String, String, String)> {
+    let (name, option) = track_assert_some!(s.split_once(':'), Failed; s);
+    let (key, value) = track_assert_some!(option.split_once('='), Failed; s);
+    Ok((name.to_owned(), key.to_owned(), value.to_owned()))
+}
+
+#[cfg(any(
+    feature = "cannyls",
+    feature = "fjall",
+    feature = "rocksdb",
+    feature = "sled",
+    feature = "sqlite"
+))]
 fn parse_size_u64(s: &str) -> Result<u64> {
     let size = Byte::from_string(s)
         .map_err(|e| track!(Failed.cause(format!("Parse Error: {:?} ({:?})", s, e))))?;
@@ -690,11 +1513,186 @@ fn stdout() -> impl Write {
     BufWriter::new(std::io::stdout())
 }
 
-#[allow(clippy::cyclomatic_complexity)]
-fn make_rocksdb_options(opt: &RocksDbOpt) -> Result<rocksdb::Options> {
-    let mut options = rocksdb::Options::default();
+#[cfg(unix)]
+fn connect_resp(address: &str) -> Result<kvs::RespKvs> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        track!(kvs::RespKvs::connect_unix(path))
+    } else {
+        track!(kvs::RespKvs::connect_tcp(address))
+    }
+}
+
+#[cfg(not(unix))]
+fn connect_resp(address: &str) -> Result<kvs::RespKvs> {
+    track_assert!(!address.starts_with("unix:"), Failed; address);
+    track!(kvs::RespKvs::connect_tcp(address))
+}
+
+fn make_fs_kvs(opt: &FsOpt, durability: Durability) -> Result<kvs::FileSystemKvs> {
+    let mut kvs = track!(kvs::FileSystemKvs::new(&opt.dir))?;
+    let layout = kvs::FileSystemLayout {
+        depth: opt.shard_depth,
+        fan_out: opt.shard_fan_out,
+        hash: match opt.shard_hash {
+            ShardHash::Sip13 => kvs::FileSystemHash::Sip13,
+            ShardHash::Sip24 => kvs::FileSystemHash::Sip24,
+            ShardHash::Fnv1a => kvs::FileSystemHash::Fnv1a,
+        },
+        key_encoding: match opt.key_encoding {
+            FsKeyEncoding::Percent => kvs::FileSystemKeyEncoding::Percent,
+            FsKeyEncoding::Hex => kvs::FileSystemKeyEncoding::Hex,
+        },
+    };
+    track!(kvs.set_layout(layout))?;
+    kvs.set_write_strategy(match opt.write_strategy {
+        WriteStrategy::InPlace => kvs::FileSystemWriteStrategy::InPlace,
+        WriteStrategy::Rename => kvs::FileSystemWriteStrategy::Rename,
+        WriteStrategy::DurableRename => kvs::FileSystemWriteStrategy::DurableRename,
+    });
+    if opt.probe_existence {
+        kvs.enable_existence_probe();
+    }
+    if opt.cleanup_empty_dirs {
+        kvs.enable_empty_dir_cleanup();
+    }
+    if durability == Durability::None {
+        kvs.disable_sync();
+    }
+    Ok(kvs)
+}
+
+#[cfg(feature = "fjall")]
+fn make_fjall(opt: &FjallOpt) -> Result<kvs::Fjall> {
+    let mut config = fjall::Config::new(&opt.dir);
+    if let Some(v) = opt.cache_size {
+        config = config.cache_size(v);
+    }
+    if let Some(v) = opt.max_write_buffer_size {
+        config = config.max_write_buffer_size(v);
+    }
+    if opt.fsync_ms.is_some() {
+        config = config.fsync_ms(opt.fsync_ms);
+    }
+
+    let mut partition_options = fjall::PartitionCreateOptions::default();
+    if let Some(v) = opt.max_memtable_size {
+        track_assert!(v <= u32::MAX as usize, Failed; v);
+        partition_options = partition_options.max_memtable_size(v as u32);
+    }
+    if let Some(v) = &opt.compaction_strategy {
+        let strategy = match v {
+            FjallCompactionStrategy::Leveled => {
+                fjall::compaction::Strategy::Leveled(Default::default())
+            }
+            FjallCompactionStrategy::SizeTiered => {
+                fjall::compaction::Strategy::SizeTiered(Default::default())
+            }
+        };
+        partition_options = partition_options.compaction_strategy(strategy);
+    }
+
+    let mut kvs = track!(kvs::Fjall::with_config(config, partition_options))?;
+    kvs.set_persist_mode(opt.persist_mode.as_ref().map(|v| match v {
+        FjallPersistMode::Buffer => fjall::PersistMode::Buffer,
+        FjallPersistMode::SyncData => fjall::PersistMode::SyncData,
+        FjallPersistMode::SyncAll => fjall::PersistMode::SyncAll,
+    }));
+    Ok(kvs)
+}
+
+#[cfg(feature = "sqlite")]
+fn make_sqlite_options(opt: &SqliteOpt) -> Result<kvs::SqliteOptions> {
+    let page_size = match opt.page_size {
+        None => None,
+        Some(v) => {
+            track_assert!(v.is_power_of_two() && (512..=65536).contains(&v), Failed; v);
+            Some(v as u32)
+        }
+    };
+    Ok(kvs::SqliteOptions {
+        journal_mode: opt.journal_mode.as_ref().map(|v| match v {
+            SqliteJournalMode::Delete => kvs::SqliteJournalMode::Delete,
+            SqliteJournalMode::Truncate => kvs::SqliteJournalMode::Truncate,
+            SqliteJournalMode::Persist => kvs::SqliteJournalMode::Persist,
+            SqliteJournalMode::Memory => kvs::SqliteJournalMode::Memory,
+            SqliteJournalMode::Wal => kvs::SqliteJournalMode::Wal,
+            SqliteJournalMode::Off => kvs::SqliteJournalMode::Off,
+        }),
+        synchronous: opt.synchronous.as_ref().map(|v| match v {
+            SqliteSynchronous::Off => kvs::SqliteSynchronous::Off,
+            SqliteSynchronous::Normal => kvs::SqliteSynchronous::Normal,
+            SqliteSynchronous::Full => kvs::SqliteSynchronous::Full,
+            SqliteSynchronous::Extra => kvs::SqliteSynchronous::Extra,
+        }),
+        page_size,
+        cache_size: opt.cache_size,
+    })
+}
+
+#[cfg(feature = "sled")]
+fn make_sled_config(opt: &SledOpt) -> sled::Config {
+    let mut config = sled::Config::new();
+    if let Some(v) = opt.cache_capacity {
+        config = config.cache_capacity(v);
+    }
+    if opt.disable_flush {
+        config = config.flush_every_ms(None);
+    } else if let Some(v) = opt.flush_every_ms {
+        config = config.flush_every_ms(Some(v));
+    }
+    if opt.use_compression {
+        config = config.use_compression(true);
+    }
+    if let Some(v) = opt.compression_factor {
+        config = config.compression_factor(v);
+    }
+    if let Some(v) = opt.segment_size {
+        config = config.segment_size(v);
+    }
+    if let Some(ref v) = opt.mode {
+        let mode = match v {
+            SledMode::LowSpace => sled::Mode::LowSpace,
+            SledMode::HighThroughput => sled::Mode::HighThroughput,
+        };
+        config = config.mode(mode);
+    }
+    config
+}
+
+#[cfg(feature = "redb")]
+fn make_redb(opt: &RedbOpt) -> Result<kvs::Redb> {
+    let mut builder = redb::Builder::new();
+    if let Some(v) = opt.cache_size {
+        builder.set_cache_size(v);
+    }
+    let mut kvs = track!(kvs::Redb::with_builder(&opt.file, builder))?;
+    kvs.set_durability(match opt.commit_durability {
+        RedbDurability::None => redb::Durability::None,
+        RedbDurability::Eventual => redb::Durability::Eventual,
+        RedbDurability::Immediate => redb::Durability::Immediate,
+        RedbDurability::Paranoid => redb::Durability::Paranoid,
+    });
+    Ok(kvs)
+}
+
+#[cfg(feature = "rocksdb")]
+#[allow(clippy::cognitive_complexity)]
+fn make_rocksdb_options(opt: &RocksDbOpt) -> Result<(rocksdb::Options, RocksDbBlockOptions)> {
     if opt.force_default {
-        return Ok(options);
+        return Ok((rocksdb::Options::default(), RocksDbBlockOptions::default()));
+    }
+
+    let mut options = if let Some(ref path) = opt.options_file {
+        track!(kvs::RocksDb::load_options_file(path))?
+    } else {
+        rocksdb::Options::default()
+    };
+    let mut block_opts = RocksDbBlockOptions::default();
+    if let Some(ref preset) = opt.preset {
+        track!(apply_rocksdb_preset(&mut options, &mut block_opts, preset))?;
+    }
+    if opt.statistics || opt.statistics_interval.is_some() {
+        options.enable_statistics();
     }
 
     if opt.disable_advise_random_on_open {
@@ -798,32 +1796,232 @@ fn make_rocksdb_options(opt: &RocksDbOpt) -> Result<rocksdb::Options> {
     } else if let Some(bucket_count) = opt.memtable_factory_hashlinklist_bucket_count {
         options.set_memtable_factory(rocksdb::MemtableFactory::HashLinkList { bucket_count });
     }
-    let mut block_opts = rocksdb::BlockBasedOptions::default();
     if let Some(v) = opt.block_opt_block_size {
-        block_opts.set_block_size(v);
+        block_opts.block_size = Some(v);
     }
     if let Some(v) = opt.block_opt_lru_cache {
-        block_opts.set_block_cache(&track_any_err!(Cache::new_lru_cache(v))?);
+        block_opts.cache = Some(Cache::new_lru_cache(v));
     }
     if opt.block_opt_disable_cache {
-        block_opts.disable_cache();
+        block_opts.disable_cache = true;
     }
     if let Some(bits_per_key) = opt.block_opt_bloom_filter_bits_per_key {
-        block_opts.set_bloom_filter(bits_per_key, opt.block_opt_bloom_filter_block_based);
+        block_opts.bloom_filter = Some((bits_per_key, opt.block_opt_bloom_filter_block_based));
     }
     if opt.block_opt_cache_index_and_filter_blocks {
-        block_opts.set_cache_index_and_filter_blocks(true);
+        block_opts.cache_index_and_filter_blocks = true;
     }
     if let Some(ref index_type) = opt.block_opt_index_type {
-        let t = match index_type {
-            BlockBasedIndexType::BinarySearch => rocksdb::BlockBasedIndexType::BinarySearch,
-            BlockBasedIndexType::HashSearch => rocksdb::BlockBasedIndexType::HashSearch,
-            BlockBasedIndexType::TwoLevelIndexSearch => {
-                rocksdb::BlockBasedIndexType::TwoLevelIndexSearch
-            }
-        };
-        block_opts.set_index_type(t);
+        block_opts.index_type = Some(index_type.clone());
+    }
+    block_opts.apply(&mut options);
+    Ok((options, block_opts))
+}
+
+/// The settings of the block-based table factory.
+///
+/// They are kept aside, since `rocksdb::Options` cannot give back its table factory,
+/// so that presets and `--block-opt-*` flags can be merged before the factory is set.
+#[cfg(feature = "rocksdb")]
+#[derive(Clone, Default)]
+struct RocksDbBlockOptions {
+    block_size: Option<usize>,
+    cache: Option<Cache>,
+    disable_cache: bool,
+    bloom_filter: Option<(f64, bool)>,
+    cache_index_and_filter_blocks: bool,
+    index_type: Option<BlockBasedIndexType>,
+}
+#[cfg(feature = "rocksdb")]
+impl RocksDbBlockOptions {
+    /// Sets the table factory, unless nothing has been set (which keeps the current factory,
+    /// e.g., the one loaded from an options file).
+    fn apply(&self, options: &mut rocksdb::Options) {
+        let is_default = self.block_size.is_none()
+            && self.cache.is_none()
+            && !self.disable_cache
+            && self.bloom_filter.is_none()
+            && !self.cache_index_and_filter_blocks
+            && self.index_type.is_none();
+        if is_default {
+            return;
+        }
+
+        let mut block_opts = rocksdb::BlockBasedOptions::default();
+        if let Some(v) = self.block_size {
+            block_opts.set_block_size(v);
+        }
+        if let Some(ref cache) = self.cache {
+            block_opts.set_block_cache(cache);
+        }
+        if self.disable_cache {
+            block_opts.disable_cache();
+        }
+        if let Some((bits_per_key, block_based)) = self.bloom_filter {
+            block_opts.set_bloom_filter(bits_per_key, block_based);
+        }
+        if self.cache_index_and_filter_blocks {
+            block_opts.set_cache_index_and_filter_blocks(true);
+        }
+        if let Some(ref index_type) = self.index_type {
+            let t = match index_type {
+                BlockBasedIndexType::BinarySearch => rocksdb::BlockBasedIndexType::BinarySearch,
+                BlockBasedIndexType::HashSearch => rocksdb::BlockBasedIndexType::HashSearch,
+                BlockBasedIndexType::TwoLevelIndexSearch => {
+                    rocksdb::BlockBasedIndexType::TwoLevelIndexSearch
+                }
+            };
+            block_opts.set_index_type(t);
+        }
+        options.set_block_based_table_factory(&block_opts);
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+fn apply_rocksdb_preset(
+    options: &mut rocksdb::Options,
+    block_opts: &mut RocksDbBlockOptions,
+    preset: &RocksDbPreset,
+) -> Result<()> {
+    const MIB: usize = 1024 * 1024;
+    match preset {
+        RocksDbPreset::PointLookup => {
+            options.optimize_for_point_lookup(64);
+        }
+        RocksDbPreset::WriteHeavy => {
+            let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+            options.increase_parallelism(parallelism as i32);
+            options.set_max_background_jobs(parallelism as i32);
+            options.set_write_buffer_size(128 * MIB);
+            options.set_max_write_buffer_number(4);
+            options.set_min_write_buffer_number_to_merge(2);
+            options.set_level_zero_slowdown_writes_trigger(40);
+            options.set_level_zero_stop_writes_trigger(64);
+        }
+        RocksDbPreset::LowMemory => {
+            options.set_write_buffer_size(4 * MIB);
+            options.set_max_write_buffer_number(2);
+            options.set_max_open_files(256);
+            block_opts.cache = Some(Cache::new_lru_cache(8 * MIB));
+            block_opts.cache_index_and_filter_blocks = true;
+        }
+        RocksDbPreset::BulkLoad => {
+            options.prepare_for_bulk_load();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+fn make_rocksdb_txn_options(opt: &RocksDbTxnOpt) -> Result<rocksdb::Options> {
+    let mut options = if let Some(ref path) = opt.options_file {
+        track!(kvs::RocksDb::load_options_file(path))?
+    } else {
+        rocksdb::Options::default()
+    };
+    if let Some(ref preset) = opt.preset {
+        let mut block_opts = RocksDbBlockOptions::default();
+        track!(apply_rocksdb_preset(&mut options, &mut block_opts, preset))?;
+        block_opts.apply(&mut options);
     }
-    options.set_block_based_table_factory(&block_opts);
     Ok(options)
 }
+
+#[cfg(feature = "rocksdb")]
+fn make_rocksdb_column_families(
+    opt: &RocksDbOpt,
+    base_options: &rocksdb::Options,
+    base_block_opts: &RocksDbBlockOptions,
+) -> Result<Vec<kvs::RocksDbColumnFamily>> {
+    let mut column_families = Vec::new();
+    for (name, prefix) in &opt.column_family {
+        let mut options = base_options.clone();
+        let mut block_opts = base_block_opts.clone();
+        for (_, key, value) in opt.cf_option.iter().filter(|o| o.0 == *name) {
+            track!(apply_rocksdb_cf_option(&mut options, &mut block_opts, key, value); name)?;
+        }
+        block_opts.apply(&mut options);
+        column_families.push(kvs::RocksDbColumnFamily {
+            name: name.clone(),
+            prefix: prefix.clone().into_bytes(),
+            options,
+        });
+    }
+    for (name, _, _) in &opt.cf_option {
+        track_assert!(
+            opt.column_family.iter().any(|(n, _)| n == name),
+            Failed,
+            "Unknown column family: {:?}",
+            name
+        );
+    }
+    Ok(column_families)
+}
+
+#[cfg(feature = "rocksdb")]
+fn apply_rocksdb_cf_option(
+    options: &mut rocksdb::Options,
+    block_opts: &mut RocksDbBlockOptions,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    match key {
+        "preset" => {
+            let preset = track_any_err!(RocksDbPreset::from_str(value, true))?;
+            track!(apply_rocksdb_preset(options, block_opts, &preset))?;
+        }
+        "write_buffer_size" => {
+            options.set_write_buffer_size(track!(parse_size(value))?);
+        }
+        "max_write_buffer_number" => {
+            options.set_max_write_buffer_number(track_any_err!(value.parse())?);
+        }
+        "target_file_size_base" => {
+            options.set_target_file_size_base(track!(parse_size_u64(value))?);
+        }
+        "optimize_for_point_lookup" => {
+            options.optimize_for_point_lookup(track_any_err!(value.parse())?);
+        }
+        "disable_auto_compactions" => {
+            options.set_disable_auto_compactions(track_any_err!(value.parse())?);
+        }
+        "bloom_filter_bits_per_key" => {
+            block_opts.bloom_filter = Some((track_any_err!(value.parse())?, false));
+        }
+        _ => track_panic!(Failed, "Unknown column family option: {:?}", key),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+fn make_rocksdb_write_options(opt: &RocksDbIoOpt) -> rocksdb::WriteOptions {
+    let mut options = rocksdb::WriteOptions::default();
+    if opt.write_opt_sync {
+        options.set_sync(true);
+    }
+    if opt.write_opt_disable_wal {
+        options.disable_wal(true);
+    }
+    if opt.write_opt_no_slowdown {
+        options.set_no_slowdown(true);
+    }
+    if opt.write_opt_low_pri {
+        options.set_low_pri(true);
+    }
+    options
+}
+
+#[cfg(feature = "rocksdb")]
+fn make_rocksdb_read_options(opt: &RocksDbIoOpt) -> rocksdb::ReadOptions {
+    let mut options = rocksdb::ReadOptions::default();
+    if opt.read_opt_disable_fill_cache {
+        options.fill_cache(false);
+    }
+    if opt.read_opt_disable_verify_checksums {
+        options.set_verify_checksums(false);
+    }
+    if let Some(v) = opt.read_opt_readahead_size {
+        options.set_readahead_size(v);
+    }
+    options
+}
@@ -0,0 +1,230 @@
+use crate::task::{CasOutcome, Divergence, Existence, UpdateOp};
+use std::collections::BTreeMap;
+
+/// In-memory reference model used to check the results reported by a key-value store.
+#[derive(Debug, Default)]
+pub struct Model {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+impl Model {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8], actual: Existence) -> Option<Divergence> {
+        let expected = self.entries.insert(key.to_vec(), value.to_vec()).is_some();
+        check_existence(expected, actual)
+    }
+
+    pub fn get(&self, key: &[u8], actual: Option<&[u8]>) -> Option<Divergence> {
+        match (self.entries.get(key), actual) {
+            (Some(expected), Some(actual)) if expected.as_slice() != actual => {
+                Some(Divergence::Value {
+                    expected_size: expected.len(),
+                    actual_size: actual.len(),
+                })
+            }
+            (expected, actual) => {
+                check_existence(expected.is_some(), Existence::new(actual.is_some()))
+            }
+        }
+    }
+
+    pub fn update(&mut self, key: &[u8], op: &UpdateOp, actual: Existence) -> Option<Divergence> {
+        let old = self.entries.get(key).map(Vec::as_slice);
+        let expected = old.is_some();
+        let new = op.apply(old);
+        self.entries.insert(key.to_vec(), new);
+        check_existence(expected, actual)
+    }
+
+    /// Applies a compare-and-swap if the current value equals `expected`.
+    ///
+    /// The reported outcome must agree with that comparison, whether it is `Swapped` or `Failed`.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        value: &[u8],
+        actual: CasOutcome,
+    ) -> Option<Divergence> {
+        let current = self.entries.get(key).map(Vec::as_slice);
+        let expected = if current == expected {
+            self.entries.insert(key.to_vec(), value.to_vec());
+            CasOutcome::Swapped
+        } else {
+            CasOutcome::Failed
+        };
+        if expected == actual {
+            None
+        } else {
+            Some(Divergence::CompareAndSwap { expected, actual })
+        }
+    }
+
+    pub fn delete(&mut self, key: &[u8], actual: Existence) -> Option<Divergence> {
+        let expected = self.entries.remove(key).is_some();
+        check_existence(expected, actual)
+    }
+
+    /// Replaces the entry of `key` with the value read from the store (`None` means absent).
+    ///
+    /// This is for writes that the store has rolled back after reporting their success.
+    pub fn reset(&mut self, key: &[u8], actual: Option<&[u8]>) {
+        match actual {
+            Some(value) => self.entries.insert(key.to_vec(), value.to_vec()),
+            None => self.entries.remove(key),
+        };
+    }
+}
+
+fn check_existence(expected: bool, actual: Existence) -> Option<Divergence> {
+    match actual.exists() {
+        Some(actual) if actual != expected => Some(Divergence::Existence { expected, actual }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs::KeyValueStore;
+    use crate::workload::{Workload, WorkloadExecutor};
+    use crate::Result;
+    use std::collections::HashMap;
+    use trackable::result::TestResult;
+
+    #[test]
+    fn agrees_with_correct_results() {
+        let mut model = Model::new();
+        assert!(model.put(b"foo", b"bar", Existence::new(false)).is_none());
+        assert!(model.put(b"foo", b"baz", Existence::new(true)).is_none());
+        assert!(model.put(b"qux", b"", Existence::unknown()).is_none());
+        assert!(model.get(b"foo", Some(b"baz")).is_none());
+        assert!(model.get(b"qux", Some(b"")).is_none());
+        assert!(model.get(b"quux", None).is_none());
+
+        let op = UpdateOp::Add(1);
+        assert!(model.update(b"n", &op, Existence::new(false)).is_none());
+        assert!(model.update(b"n", &op, Existence::unknown()).is_none());
+        assert!(model.get(b"n", Some(&2u64.to_be_bytes())).is_none());
+
+        let cas = CasOutcome::Swapped;
+        assert!(model
+            .compare_and_swap(b"foo", Some(b"baz"), b"x", cas)
+            .is_none());
+        let cas = CasOutcome::Failed;
+        assert!(model.compare_and_swap(b"foo", None, b"y", cas).is_none());
+        assert!(model.get(b"foo", Some(b"x")).is_none());
+
+        assert!(model.delete(b"foo", Existence::new(true)).is_none());
+        assert!(model.delete(b"foo", Existence::new(false)).is_none());
+        assert!(model.get(b"foo", None).is_none());
+    }
+
+    #[test]
+    fn reports_wrong_results() {
+        let mut model = Model::new();
+        assert!(matches!(
+            model.put(b"foo", b"bar", Existence::new(true)),
+            Some(Divergence::Existence {
+                expected: false,
+                actual: true
+            })
+        ));
+        assert!(matches!(
+            model.get(b"foo", Some(b"ba")),
+            Some(Divergence::Value {
+                expected_size: 3,
+                actual_size: 2
+            })
+        ));
+        assert!(matches!(
+            model.get(b"foo", None),
+            Some(Divergence::Existence {
+                expected: true,
+                actual: false
+            })
+        ));
+
+        // The model follows the operations even if their results diverge.
+        let op = UpdateOp::Append(b"!".to_vec());
+        assert!(matches!(
+            model.update(b"foo", &op, Existence::new(false)),
+            Some(Divergence::Existence {
+                expected: true,
+                actual: false
+            })
+        ));
+        assert!(matches!(
+            model.compare_and_swap(b"foo", Some(b"bar"), b"x", CasOutcome::Swapped),
+            Some(Divergence::CompareAndSwap {
+                expected: CasOutcome::Failed,
+                actual: CasOutcome::Swapped
+            })
+        ));
+        assert!(matches!(
+            model.compare_and_swap(b"foo", Some(b"bar!"), b"x", CasOutcome::Failed),
+            Some(Divergence::CompareAndSwap {
+                expected: CasOutcome::Swapped,
+                actual: CasOutcome::Failed
+            })
+        ));
+        assert!(model.get(b"foo", Some(b"x")).is_none());
+        assert!(matches!(
+            model.delete(b"qux", Existence::new(true)),
+            Some(Divergence::Existence {
+                expected: false,
+                actual: true
+            })
+        ));
+    }
+
+    #[test]
+    fn executor_reports_divergences() -> TestResult {
+        let workload: Workload = track_any_err!(serde_json::from_str(
+            r#"[
+              {"PUT": {"key": "foo", "value": {"size": 3}}},
+              {"GET": {"key": "foo"}},
+              {"GET": {"key": "bar"}}
+            ]"#
+        ))?;
+        let mut executor = WorkloadExecutor::new(CorruptingStore::default(), workload);
+        executor.enable_model_check();
+        let results = executor.collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].divergence.is_none());
+        assert!(matches!(
+            results[1].divergence,
+            Some(Divergence::Value {
+                expected_size: 3,
+                actual_size: 4
+            })
+        ));
+        assert!(results[2].divergence.is_none());
+        Ok(())
+    }
+
+    /// A store that appends a byte to every value it returns.
+    #[derive(Debug, Default)]
+    struct CorruptingStore(HashMap<Vec<u8>, Vec<u8>>);
+    impl KeyValueStore for CorruptingStore {
+        type OwnedValue = Vec<u8>;
+
+        fn put(&mut self, key: &[u8], value: &[u8]) -> Result<Existence> {
+            track!(self.0.put(key, value))
+        }
+
+        fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            let mut value = track!(KeyValueStore::get(&mut self.0, key))?;
+            if let Some(ref mut v) = value {
+                v.push(0);
+            }
+            Ok(value)
+        }
+
+        fn delete(&mut self, key: &[u8]) -> Result<Existence> {
+            track!(self.0.delete(key))
+        }
+    }
+}
@@ -2,7 +2,6 @@ use crate::task::TaskResult;
 use crate::Result;
 use gnuplot::{AutoOption, AxesCommon, Figure, Rotate};
 use rand::{self, Rng};
-use std::f64::EPSILON;
 use trackable::error::Failed;
 
 #[derive(Debug)]
@@ -41,7 +40,7 @@ impl PlotOptions {
         let xs = data.iter().map(|t| t.0);
         let ys = data.iter().map(|t| t.1);
 
-        let x_label = if (self.sampling_rate - 1.0).abs() < EPSILON {
+        let x_label = if (self.sampling_rate - 1.0).abs() < f64::EPSILON {
             "Sequence Number".to_owned()
         } else {
             format!("Sequence Number (sampling-rate={})", self.sampling_rate)
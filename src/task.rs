@@ -1,35 +1,121 @@
 use crate::Result;
 use rand::{self, RngCore};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str;
 use std::time::Duration;
 use trackable::error::Failure;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Task {
-    Put { key: Key, value: ValueSpec },
-    Get { key: Key },
-    Delete { key: Key },
+    Put {
+        key: Key,
+        value: ValueSpec,
+    },
+    Get {
+        key: Key,
+    },
+    Delete {
+        key: Key,
+    },
+
+    /// Reads all `keys` and then writes `value` to each of them in a single transaction.
+    Transaction {
+        keys: Vec<Key>,
+        value: ValueSpec,
+    },
+
+    /// Replaces the value of `key` with `value` if the current value is unchanged.
+    ///
+    /// If `expect_absent` is `true`, the swap succeeds only if `key` does not exist.
+    /// Otherwise, the current value is read just before the swap (not included in the latency).
+    CompareAndSwap {
+        key: Key,
+        value: ValueSpec,
+        #[serde(default)]
+        expect_absent: bool,
+    },
+
+    /// Reads the values of `keys` in a single call.
+    MultiGet {
+        keys: Vec<Key>,
+    },
+
+    /// Reads, transforms and writes the value of `key` as one operation.
+    Update {
+        key: Key,
+        op: UpdateSpec,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Key(String);
+/// A workload key.
+///
+/// Keys that are valid UTF-8 are encoded as plain JSON strings and
+/// the others as `{"hex": "..."}` objects.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Key(Vec<u8>);
 impl Key {
     pub fn new(s: String) -> Self {
-        Key(s)
+        Key(s.into_bytes())
     }
 
     pub fn from_utf8(b: Vec<u8>) -> Result<Self> {
         let s = track_any_err!(String::from_utf8(b))?;
-        Ok(Key(s))
+        Ok(Key::new(s))
+    }
+
+    pub fn from_bytes(b: Vec<u8>) -> Self {
+        Key(b)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
     }
 }
 impl AsRef<[u8]> for Key {
     fn as_ref(&self) -> &[u8] {
-        self.0.as_bytes()
+        &self.0
+    }
+}
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if let Ok(s) = str::from_utf8(&self.0) {
+            serializer.serialize_str(s)
+        } else {
+            let hex = self.0.iter().map(|b| format!("{:02x}", b)).collect();
+            KeyRepr::Hex { hex }.serialize(serializer)
+        }
+    }
+}
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match KeyRepr::deserialize(deserializer)? {
+            KeyRepr::Utf8(s) => Ok(Key::new(s)),
+            KeyRepr::Hex { hex } => {
+                if hex.len() % 2 != 0 || !hex.is_ascii() {
+                    return Err(D::Error::custom(format!("malformed hex key: {:?}", hex)));
+                }
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map(Key)
+                    .map_err(|e| D::Error::custom(format!("malformed hex key: {:?} ({})", hex, e)))
+            }
+        }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum KeyRepr {
+    Utf8(String),
+    Hex { hex: String },
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ValueSpec {
@@ -44,23 +130,212 @@ impl ValueSpec {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateSpec {
+    Add(u64),
+    Append(ValueSpec),
+}
+impl UpdateSpec {
+    pub fn generate(&self) -> UpdateOp {
+        match self {
+            UpdateSpec::Add(n) => UpdateOp::Add(*n),
+            UpdateSpec::Append(value) => UpdateOp::Append(value.generate()),
+        }
+    }
+}
+
+/// A transformation applied to a value by `KeyValueStore::update`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOp {
+    /// Adds to a big-endian 64-bit counter (values of other sizes are regarded as zero).
+    Add(u64),
+
+    /// Appends bytes to a value.
+    Append(Vec<u8>),
+}
+impl UpdateOp {
+    /// Returns the result of applying this operation to `old` (`None` means an absent value).
+    pub fn apply(&self, old: Option<&[u8]>) -> Vec<u8> {
+        match self {
+            UpdateOp::Add(n) => {
+                let mut counter = [0; 8];
+                if let Some(old) = old.filter(|old| old.len() == 8) {
+                    counter.copy_from_slice(old);
+                }
+                u64::from_be_bytes(counter)
+                    .wrapping_add(*n)
+                    .to_be_bytes()
+                    .to_vec()
+            }
+            UpdateOp::Append(bytes) => {
+                let mut value = old.map(<[u8]>::to_vec).unwrap_or_default();
+                value.extend_from_slice(bytes);
+                value
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Method {
     Put,
     Get,
     Delete,
+    Transaction,
+    CompareAndSwap,
+    Update,
+    MultiGet,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TaskResult {
     pub seqno: usize,
+
+    /// The key of the task (the first key for transactions and multi-gets).
     pub key: Key,
     pub method: Method,
     pub start_time: Seconds,
     pub elapsed: Seconds,
     pub exists: Existence,
     pub error: Option<Failure>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub divergence: Option<Divergence>,
+
+    /// Time spent on probing key existence (not included in `elapsed`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_elapsed: Option<Seconds>,
+
+    /// Time spent on `KeyValueStore::sync` after the task (not included in `elapsed`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_elapsed: Option<Seconds>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TxnOutcome>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compare_and_swap: Option<CasOutcome>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multi_get: Option<MultiGetOutcome>,
+}
+impl TaskResult {
+    /// Makes a successful result with unknown existence and no optional outcomes.
+    pub fn new(
+        seqno: usize,
+        key: Key,
+        method: Method,
+        start_time: Seconds,
+        elapsed: Seconds,
+    ) -> Self {
+        TaskResult {
+            seqno,
+            key,
+            method,
+            start_time,
+            elapsed,
+            exists: Existence::unknown(),
+            error: None,
+            divergence: None,
+            probe_elapsed: None,
+            sync_elapsed: None,
+            transaction: None,
+            compare_and_swap: None,
+            multi_get: None,
+        }
+    }
+}
+
+/// The numbers of keys requested and found by a multi-get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiGetOutcome {
+    pub keys: usize,
+    pub found: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CasOutcome {
+    Swapped,
+
+    /// The current value did not match the expected one.
+    Failed,
+}
+
+/// The outcome of a transaction that did not fail with an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum TxnOutcome {
+    Committed {
+        retries: usize,
+    },
+
+    /// Gave up because every attempt failed with a commit conflict.
+    Conflicted {
+        retries: usize,
+    },
+}
+impl TxnOutcome {
+    pub fn retries(self) -> usize {
+        match self {
+            TxnOutcome::Committed { retries } | TxnOutcome::Conflicted { retries } => retries,
+        }
+    }
+}
+
+/// Backend-specific information attached to a run result.
+pub type Metadata = BTreeMap<String, serde_json::Value>;
+
+/// The output of the `run` command.
+///
+/// A bare JSON array of task results (the format of older versions) is also accepted.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "RunResultRepr")]
+pub struct RunResult {
+    pub results: Vec<TaskResult>,
+    pub metadata: Metadata,
+}
+impl From<RunResultRepr> for RunResult {
+    fn from(f: RunResultRepr) -> Self {
+        match f {
+            RunResultRepr::Results(results) => RunResult {
+                results,
+                metadata: Metadata::new(),
+            },
+            RunResultRepr::WithMetadata { results, metadata } => RunResult { results, metadata },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RunResultRepr {
+    Results(Vec<TaskResult>),
+    WithMetadata {
+        results: Vec<TaskResult>,
+        #[serde(default)]
+        metadata: Metadata,
+    },
+}
+
+/// A mismatch between the result reported by a key-value store and the reference model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum Divergence {
+    Existence {
+        expected: bool,
+        actual: bool,
+    },
+    Value {
+        expected_size: usize,
+        actual_size: usize,
+    },
+    CompareAndSwap {
+        expected: CasOutcome,
+        actual: CasOutcome,
+    },
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -79,7 +354,7 @@ impl Existence {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialOrd, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct Seconds(f64);
 impl Seconds {
     pub fn new(duration: Duration) -> Self {
@@ -91,6 +366,11 @@ impl Seconds {
         self.0
     }
 }
+impl PartialOrd for Seconds {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 impl Ord for Seconds {
     fn cmp(&self, other: &Self) -> Ordering {
         self.0.partial_cmp(&other.0).expect("Never fails")
@@ -1,6 +1,9 @@
 use crate::kvs::KeyValueStore;
-use crate::task::{Existence, Method, Seconds, Task, TaskResult};
-use std::time::Instant;
+use crate::model::Model;
+use crate::task::{
+    CasOutcome, Existence, Key, Method, MultiGetOutcome, Seconds, Task, TaskResult, TxnOutcome,
+};
+use std::time::{Duration, Instant};
 use std::vec;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,12 +18,27 @@ impl Workload {
     }
 }
 
+/// When a `WorkloadExecutor` makes writes durable by calling `KeyValueStore::sync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Never syncs.
+    None,
+
+    /// Syncs once after the last task.
+    FlushAtEnd,
+
+    /// Syncs after every `N` tasks and after the last task.
+    Every(usize),
+}
+
 #[derive(Debug)]
 pub struct WorkloadExecutor<T> {
     kvs: T,
     workload: vec::IntoIter<Task>,
     start_time: Instant,
     seqno: usize,
+    model: Option<Model>,
+    durability: Durability,
 }
 impl<T: KeyValueStore> WorkloadExecutor<T> {
     pub fn new(kvs: T, workload: Workload) -> Self {
@@ -29,6 +47,64 @@ impl<T: KeyValueStore> WorkloadExecutor<T> {
             workload: workload.0.into_iter(),
             start_time: Instant::now(),
             seqno: 0,
+            model: None,
+            durability: Durability::None,
+        }
+    }
+
+    /// Mirrors every operation into an in-memory model and reports divergences from it.
+    pub fn enable_model_check(&mut self) {
+        self.model = Some(Model::new());
+    }
+
+    pub fn kvs_mut(&mut self) -> &mut T {
+        &mut self.kvs
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    fn sync_if_needed(&mut self, mut result: TaskResult) -> TaskResult {
+        for key in self.kvs.take_discarded_keys() {
+            if let Some(model) = &mut self.model {
+                match self.kvs.get(&key) {
+                    Ok(value) => model.reset(&key, value.as_ref().map(AsRef::as_ref)),
+                    Err(e) => {
+                        result.error.get_or_insert(e);
+                    }
+                }
+            }
+        }
+        if let Err(e) = self.kvs.after_task() {
+            result.error.get_or_insert(e);
+        }
+
+        let is_last = self.workload.len() == 0;
+        let do_sync = match self.durability {
+            Durability::None => false,
+            Durability::FlushAtEnd => is_last,
+            Durability::Every(n) => (result.seqno + 1) % n == 0 || is_last,
+        };
+        if do_sync {
+            let start_time = Instant::now();
+            if let Err(e) = self.kvs.sync() {
+                result.error.get_or_insert(e);
+            }
+            result.sync_elapsed = Some(Seconds::new(start_time.elapsed()));
+        }
+        result
+    }
+
+    fn elapsed(&mut self, start_time: Duration, end_time: Duration) -> (Seconds, Option<Seconds>) {
+        let elapsed = end_time - start_time;
+        if let Some(probe_elapsed) = self.kvs.take_probe_elapsed() {
+            (
+                Seconds::new(elapsed.saturating_sub(probe_elapsed)),
+                Some(Seconds::new(probe_elapsed)),
+            )
+        } else {
+            (Seconds::new(elapsed), None)
         }
     }
 }
@@ -44,60 +120,209 @@ impl<T: KeyValueStore> Iterator for WorkloadExecutor<T> {
                 let start_time = self.start_time.elapsed();
                 let result = self.kvs.put(key.as_ref(), &value);
                 let end_time = self.start_time.elapsed();
-                let result = TaskResult {
-                    seqno,
-                    key,
-                    method: Method::Put,
-                    start_time: Seconds::new(start_time),
-                    elapsed: Seconds::new(end_time - start_time),
-                    exists: result
-                        .as_ref()
-                        .ok()
-                        .cloned()
-                        .unwrap_or_else(Existence::unknown),
-                    error: result.err(),
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                let divergence = match (&mut self.model, &result) {
+                    (Some(model), Ok(exists)) => model.put(key.as_ref(), &value, *exists),
+                    _ => None,
                 };
-                Some(result)
+                let mut r =
+                    TaskResult::new(seqno, key, Method::Put, Seconds::new(start_time), elapsed);
+                match result {
+                    Ok(exists) => r.exists = exists,
+                    Err(e) => r.error = Some(e),
+                }
+                r.divergence = divergence;
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
             }
             Some(Task::Get { key, .. }) => {
                 let start_time = self.start_time.elapsed();
                 let result = self.kvs.get(key.as_ref());
                 let end_time = self.start_time.elapsed();
-                let result = TaskResult {
-                    seqno,
-                    key,
-                    method: Method::Get,
-                    start_time: Seconds::new(start_time),
-                    elapsed: Seconds::new(end_time - start_time),
-                    exists: result
-                        .as_ref()
-                        .ok()
-                        .map(|v| Existence::new(v.is_some()))
-                        .unwrap_or_else(Existence::unknown),
-                    error: result.err(),
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                let divergence = match (&self.model, &result) {
+                    (Some(model), Ok(value)) => {
+                        model.get(key.as_ref(), value.as_ref().map(AsRef::as_ref))
+                    }
+                    _ => None,
                 };
-                Some(result)
+                let mut r =
+                    TaskResult::new(seqno, key, Method::Get, Seconds::new(start_time), elapsed);
+                match result {
+                    Ok(value) => r.exists = Existence::new(value.is_some()),
+                    Err(e) => r.error = Some(e),
+                }
+                r.divergence = divergence;
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
             }
             Some(Task::Delete { key, .. }) => {
                 let start_time = self.start_time.elapsed();
                 let result = self.kvs.delete(key.as_ref());
                 let end_time = self.start_time.elapsed();
-                let result = TaskResult {
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                let divergence = match (&mut self.model, &result) {
+                    (Some(model), Ok(exists)) => model.delete(key.as_ref(), *exists),
+                    _ => None,
+                };
+                let mut r = TaskResult::new(
                     seqno,
                     key,
-                    method: Method::Delete,
-                    start_time: Seconds::new(start_time),
-                    elapsed: Seconds::new(end_time - start_time),
-                    exists: result
-                        .as_ref()
-                        .ok()
-                        .cloned()
-                        .unwrap_or_else(Existence::unknown),
-                    error: result.err(),
+                    Method::Delete,
+                    Seconds::new(start_time),
+                    elapsed,
+                );
+                match result {
+                    Ok(exists) => r.exists = exists,
+                    Err(e) => r.error = Some(e),
+                }
+                r.divergence = divergence;
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
+            }
+            Some(Task::MultiGet { keys }) => {
+                let key_refs = keys.iter().map(AsRef::as_ref).collect::<Vec<&[u8]>>();
+                let start_time = self.start_time.elapsed();
+                let result = self.kvs.multi_get(&key_refs);
+                let end_time = self.start_time.elapsed();
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                let divergence = match (&self.model, &result) {
+                    (Some(model), Ok(values)) => key_refs
+                        .iter()
+                        .zip(values)
+                        .find_map(|(key, value)| model.get(key, value.as_ref().map(AsRef::as_ref))),
+                    _ => None,
+                };
+                let mut r = TaskResult::new(
+                    seqno,
+                    first_key(keys),
+                    Method::MultiGet,
+                    Seconds::new(start_time),
+                    elapsed,
+                );
+                match result {
+                    Ok(values) => {
+                        r.multi_get = Some(MultiGetOutcome {
+                            keys: values.len(),
+                            found: values.iter().filter(|v| v.is_some()).count(),
+                        })
+                    }
+                    Err(e) => r.error = Some(e),
+                }
+                r.divergence = divergence;
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
+            }
+            Some(Task::Update { key, op }) => {
+                let op = op.generate();
+                let start_time = self.start_time.elapsed();
+                let result = self.kvs.update(key.as_ref(), &op);
+                let end_time = self.start_time.elapsed();
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                let divergence = match (&mut self.model, &result) {
+                    (Some(model), Ok(exists)) => model.update(key.as_ref(), &op, *exists),
+                    _ => None,
+                };
+                let mut r = TaskResult::new(
+                    seqno,
+                    key,
+                    Method::Update,
+                    Seconds::new(start_time),
+                    elapsed,
+                );
+                match result {
+                    Ok(exists) => r.exists = exists,
+                    Err(e) => r.error = Some(e),
+                }
+                r.divergence = divergence;
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
+            }
+            Some(Task::Transaction { keys, value }) => {
+                let value = value.generate();
+                let key_refs = keys.iter().map(AsRef::as_ref).collect::<Vec<&[u8]>>();
+                let start_time = self.start_time.elapsed();
+                let result = self.kvs.transaction(&key_refs, &value);
+                let end_time = self.start_time.elapsed();
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                if let (Some(model), Ok(TxnOutcome::Committed { .. })) = (&mut self.model, &result)
+                {
+                    for key in &key_refs {
+                        model.put(key, &value, Existence::unknown());
+                    }
+                }
+                let mut r = TaskResult::new(
+                    seqno,
+                    first_key(keys),
+                    Method::Transaction,
+                    Seconds::new(start_time),
+                    elapsed,
+                );
+                match result {
+                    Ok(outcome) => r.transaction = Some(outcome),
+                    Err(e) => r.error = Some(e),
+                }
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
+            }
+            Some(Task::CompareAndSwap {
+                key,
+                value,
+                expect_absent,
+            }) => {
+                let value = value.generate();
+                let expected = if expect_absent {
+                    Ok(None)
+                } else {
+                    self.kvs
+                        .get(key.as_ref())
+                        .map(|v| v.map(|v| v.as_ref().to_vec()))
                 };
-                Some(result)
+                let start_time = self.start_time.elapsed();
+                let (expected, result) = match expected {
+                    Ok(expected) => {
+                        let result =
+                            self.kvs
+                                .compare_and_swap(key.as_ref(), expected.as_deref(), &value);
+                        (expected, result)
+                    }
+                    Err(e) => (None, Err(e)),
+                };
+                let end_time = self.start_time.elapsed();
+                let (elapsed, probe_elapsed) = self.elapsed(start_time, end_time);
+                let exists = match result {
+                    Ok(outcome) if expect_absent => Existence::new(outcome == CasOutcome::Failed),
+                    _ => Existence::unknown(),
+                };
+                let divergence = match (&mut self.model, &result) {
+                    (Some(model), Ok(outcome)) => {
+                        model.compare_and_swap(key.as_ref(), expected.as_deref(), &value, *outcome)
+                    }
+                    _ => None,
+                };
+                let mut r = TaskResult::new(
+                    seqno,
+                    key,
+                    Method::CompareAndSwap,
+                    Seconds::new(start_time),
+                    elapsed,
+                );
+                match result {
+                    Ok(outcome) => r.compare_and_swap = Some(outcome),
+                    Err(e) => r.error = Some(e),
+                }
+                r.exists = exists;
+                r.divergence = divergence;
+                r.probe_elapsed = probe_elapsed;
+                Some(self.sync_if_needed(r))
             }
             None => None,
         }
     }
 }
+
+fn first_key(keys: Vec<Key>) -> Key {
+    keys.into_iter()
+        .next()
+        .unwrap_or_else(|| Key::from_bytes(Vec::new()))
+}
@@ -0,0 +1,534 @@
+#[macro_use]
+extern crate trackable;
+
+use ekvsb::kvs::{self, testing};
+use std::collections::{BTreeMap, HashMap};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::thread;
+use tempfile::TempDir;
+use trackable::result::TestResult;
+
+#[test]
+fn hashmap_conforms() -> TestResult {
+    track!(testing::run_conformance(|| Ok(HashMap::new())))?;
+    Ok(())
+}
+
+#[test]
+fn btreemap_conforms() -> TestResult {
+    track!(testing::run_conformance(|| Ok(BTreeMap::new())))?;
+    Ok(())
+}
+
+#[test]
+fn fs_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::FileSystemKvs::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[test]
+fn fs_with_existence_probe_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let mut kvs = track!(kvs::FileSystemKvs::new(dir.path().join(i.to_string())))?;
+        kvs.enable_existence_probe();
+        Ok(kvs)
+    }))?;
+    Ok(())
+}
+
+#[test]
+fn fs_with_durable_rename_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let mut kvs = track!(kvs::FileSystemKvs::new(dir.path().join(i.to_string())))?;
+        kvs.set_write_strategy(kvs::FileSystemWriteStrategy::DurableRename);
+        kvs.enable_existence_probe();
+        Ok(kvs)
+    }))?;
+    Ok(())
+}
+
+#[test]
+fn fs_with_flat_hex_layout_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let mut kvs = track!(kvs::FileSystemKvs::new(dir.path().join(i.to_string())))?;
+        track!(kvs.set_layout(kvs::FileSystemLayout {
+            depth: 0,
+            key_encoding: kvs::FileSystemKeyEncoding::Hex,
+            ..Default::default()
+        }))?;
+        kvs.enable_empty_dir_cleanup();
+        kvs.enable_existence_probe();
+        Ok(kvs)
+    }))?;
+    Ok(())
+}
+
+#[test]
+fn fs_accepts_long_and_empty_keys() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let layouts = [
+        kvs::FileSystemLayout::default(),
+        kvs::FileSystemLayout {
+            depth: 0,
+            ..Default::default()
+        },
+        kvs::FileSystemLayout {
+            depth: 0,
+            key_encoding: kvs::FileSystemKeyEncoding::Hex,
+            ..Default::default()
+        },
+    ];
+    for (i, layout) in layouts.iter().enumerate() {
+        let mut kvs = track!(kvs::FileSystemKvs::new(dir.path().join(i.to_string())))?;
+        track!(kvs.set_layout(layout.clone()))?;
+        kvs.enable_existence_probe();
+        track!(testing::check_long_keys(&mut kvs))?;
+        track!(testing::check_empty_key(&mut kvs))?;
+        track!(testing::check_path_like_keys(&mut kvs))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn resp_conforms() -> TestResult {
+    track!(testing::run_conformance(|| {
+        let listener = track_any_err!(TcpListener::bind("127.0.0.1:0"))?;
+        let addr = track_any_err!(listener.local_addr())?;
+        thread::spawn(move || -> ekvsb::Result<()> {
+            let (stream, _) = track_any_err!(listener.accept())?;
+            let reader = track_any_err!(stream.try_clone())?;
+            track!(kvs::resp::serve(&mut HashMap::new(), reader, stream))
+        });
+        kvs::RespKvs::connect_tcp(addr)
+    }))?;
+    Ok(())
+}
+
+#[test]
+fn resp_fails_after_malformed_reply() -> TestResult {
+    use ekvsb::kvs::KeyValueStore;
+
+    // An unknown type.
+    let mut kvs = track!(connect_resp_replying(b"?\r\n".to_vec()))?;
+    assert!(kvs.get(b"foo").is_err());
+    assert!(kvs.get(b"foo").is_err());
+    Ok(())
+}
+
+#[test]
+fn resp_rejects_malformed_headers() -> TestResult {
+    use ekvsb::kvs::KeyValueStore;
+
+    let replies = vec![
+        // Lengths that must not be allocated before the data arrives.
+        b"$1099511627776\r\n".to_vec(),
+        b"$536870911\r\nfoo".to_vec(),
+        b"*1099511627776\r\n$3\r\nfoo\r\n".to_vec(),
+        // Nesting that must not exhaust the stack.
+        b"*1\r\n".repeat(100_000),
+    ];
+    for reply in replies {
+        let mut kvs = track!(connect_resp_replying(reply))?;
+        assert!(kvs.get(b"foo").is_err());
+        assert!(kvs.get(b"foo").is_err());
+    }
+    Ok(())
+}
+
+/// Connects to a server that sends `reply` and closes its sending side.
+fn connect_resp_replying(reply: Vec<u8>) -> ekvsb::Result<kvs::RespKvs> {
+    use std::io::{self, Write};
+    use std::net::Shutdown;
+
+    let listener = track_any_err!(TcpListener::bind("127.0.0.1:0"))?;
+    let addr = track_any_err!(listener.local_addr())?;
+    thread::spawn(move || -> ekvsb::Result<()> {
+        let (mut stream, _) = track_any_err!(listener.accept())?;
+        track_any_err!(stream.write_all(&reply))?;
+        track_any_err!(stream.shutdown(Shutdown::Write))?;
+        track_any_err!(io::copy(&mut stream, &mut io::sink()))?;
+        Ok(())
+    });
+    track!(kvs::RespKvs::connect_tcp(addr))
+}
+
+#[cfg(unix)]
+#[test]
+fn resp_over_unix_socket_with_set_get_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let path = dir.path().join(format!("{}.sock", i));
+        let listener = track_any_err!(UnixListener::bind(&path))?;
+        thread::spawn(move || -> ekvsb::Result<()> {
+            let (stream, _) = track_any_err!(listener.accept())?;
+            let reader = track_any_err!(stream.try_clone())?;
+            track!(kvs::resp::serve(&mut BTreeMap::new(), reader, stream))
+        });
+        let mut kvs = track!(kvs::RespKvs::connect_unix(path))?;
+        kvs.enable_set_get();
+        Ok(kvs)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::RocksDb::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_with_existence_probe_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let mut kvs = track!(kvs::RocksDb::new(dir.path().join(i.to_string())))?;
+        kvs.enable_existence_probe();
+        Ok(kvs)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_with_column_families_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let column_families = vec![kvs::RocksDbColumnFamily {
+            name: "a".to_owned(),
+            prefix: b"a".to_vec(),
+            options: rocksdb::Options::default(),
+        }];
+        kvs::RocksDb::with_column_families(
+            dir.path().join(i.to_string()),
+            rocksdb::Options::default(),
+            column_families,
+        )
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_txn_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::RocksDbTxn::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_txn_retries_on_lock_conflicts() -> TestResult {
+    use ekvsb::kvs::KeyValueStore;
+    use ekvsb::task::TxnOutcome;
+    use std::time::Duration;
+
+    let dir = track_any_err!(TempDir::new())?;
+    let mut kvs = track!(kvs::RocksDbTxn::new(dir.path()))?;
+    kvs.set_max_retries(2);
+    kvs.set_lock_timeout(Duration::from_millis(10));
+
+    // Another transaction locks `foo` until it is rolled back.
+    let db = kvs.db();
+    let other = db.transaction();
+    track_any_err!(other.get_for_update(b"foo", true))?;
+    let outcome = track!(kvs.transaction(&[&b"foo"[..], &b"bar"[..]], b"1"))?;
+    assert_eq!(outcome, TxnOutcome::Conflicted { retries: 2 });
+
+    track_any_err!(other.rollback())?;
+    let outcome = track!(kvs.transaction(&[&b"foo"[..], &b"bar"[..]], b"1"))?;
+    assert_eq!(outcome, TxnOutcome::Committed { retries: 0 });
+    assert_eq!(track!(kvs.get(b"foo"))?, Some(b"1".to_vec()));
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_optimistic_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::RocksDbOptimistic::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_txn_stores_sync() -> TestResult {
+    use ekvsb::kvs::KeyValueStore;
+
+    let dir = track_any_err!(TempDir::new())?;
+    let mut kvs = track!(kvs::RocksDbOptimistic::new(dir.path().join("optimistic")))?;
+    track!(kvs.put(b"foo", b"bar"))?;
+    track!(kvs.sync())?;
+    kvs.disable_wal();
+    track!(kvs.put(b"foo", b"baz"))?;
+    track!(kvs.sync())?;
+
+    let mut kvs = track!(kvs::RocksDbTxn::new(dir.path().join("txn")))?;
+    assert!(kvs.sync().is_err());
+    Ok(())
+}
+
+#[cfg(feature = "sled")]
+#[test]
+fn sled_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::SledTree::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "redb")]
+#[test]
+fn redb_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::Redb::new(dir.path().join(format!("{}.redb", i)))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::Sqlite::new(dir.path().join(format!("{}.sqlite", i)))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_with_wal_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let options = kvs::SqliteOptions {
+            journal_mode: Some(kvs::SqliteJournalMode::Wal),
+            synchronous: Some(kvs::SqliteSynchronous::Normal),
+            page_size: Some(8192),
+            cache_size: Some(4 * 1024 * 1024),
+        };
+        kvs::Sqlite::with_options(dir.path().join(format!("{}.sqlite", i)), options)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_syncs_unless_synchronous_is_off() -> TestResult {
+    use ekvsb::kvs::KeyValueStore;
+
+    let dir = track_any_err!(TempDir::new())?;
+    let options = kvs::SqliteOptions {
+        journal_mode: Some(kvs::SqliteJournalMode::Wal),
+        synchronous: Some(kvs::SqliteSynchronous::Normal),
+        ..kvs::SqliteOptions::default()
+    };
+    let mut kvs = track!(kvs::Sqlite::with_options(
+        dir.path().join("wal.sqlite"),
+        options
+    ))?;
+    track!(kvs.put(b"foo", b"bar"))?;
+    track!(kvs.sync())?;
+
+    let options = kvs::SqliteOptions {
+        synchronous: Some(kvs::SqliteSynchronous::Off),
+        ..kvs::SqliteOptions::default()
+    };
+    let mut kvs = track!(kvs::Sqlite::with_options(
+        dir.path().join("off.sqlite"),
+        options
+    ))?;
+    track!(kvs.put(b"foo", b"bar"))?;
+    assert!(kvs.sync().is_err());
+    Ok(())
+}
+
+#[cfg(feature = "lmdb")]
+#[test]
+fn lmdb_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::Lmdb::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "lmdb")]
+#[test]
+fn lmdb_with_grouped_commits_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let options = kvs::LmdbOptions {
+            no_sync: true,
+            ops_per_txn: 3,
+            ..kvs::LmdbOptions::default()
+        };
+        kvs::Lmdb::with_options(dir.path().join(i.to_string()), options)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "lmdb")]
+#[test]
+fn lmdb_with_existence_probe_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        let options = kvs::LmdbOptions {
+            no_sync: true,
+            ops_per_txn: 3,
+            ..kvs::LmdbOptions::default()
+        };
+        let mut kvs = track!(kvs::Lmdb::with_options(
+            dir.path().join(i.to_string()),
+            options
+        ))?;
+        kvs.enable_existence_probe();
+        Ok(kvs)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "lmdb")]
+#[test]
+fn lmdb_reports_writes_discarded_by_grouped_abort() -> TestResult {
+    use ekvsb::workload::{Workload, WorkloadExecutor};
+
+    let dir = track_any_err!(TempDir::new())?;
+    let options = kvs::LmdbOptions {
+        no_sync: true,
+        ops_per_txn: 3,
+        ..kvs::LmdbOptions::default()
+    };
+    let kvs = track!(kvs::Lmdb::with_options(dir.path(), options))?;
+
+    // LMDB rejects the empty key, which aborts the group including the put of `foo`.
+    let workload: Workload = track_any_err!(serde_json::from_str(
+        r#"[
+          {"PUT": {"key": "foo", "value": {"size": 3}}},
+          {"PUT": {"key": "", "value": {"size": 3}}},
+          {"GET": {"key": "foo"}}
+        ]"#
+    ))?;
+    let mut executor = WorkloadExecutor::new(kvs, workload);
+    executor.enable_model_check();
+    let results = (&mut executor).collect::<Vec<_>>();
+    assert!(results[0].error.is_none());
+    assert!(results[1].error.is_some());
+    assert!(results[2].error.is_none());
+    assert!(results.iter().all(|r| r.divergence.is_none()));
+    Ok(())
+}
+
+#[cfg(feature = "fjall")]
+#[test]
+fn fjall_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::Fjall::new(dir.path().join(i.to_string()))
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "cannyls")]
+#[test]
+fn cannyls_storage_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let options = cannyls_options();
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::CannyLsStorage::new(dir.path().join(format!("{}.lusf", i)), &options)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "cannyls")]
+#[test]
+fn cannyls_device_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let options = cannyls_options();
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::CannyLsDevice::new(dir.path().join(format!("{}.lusf", i)), &options)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "cannyls")]
+#[test]
+fn cannyls_storage_with_checked_hash_conforms() -> TestResult {
+    let dir = track_any_err!(TempDir::new())?;
+    let options = kvs::CannyLsOptions {
+        key_mode: kvs::CannyLsKeyMode::CheckedHash,
+        ..cannyls_options()
+    };
+    let mut i = 0;
+    track!(testing::run_conformance(|| {
+        i += 1;
+        kvs::CannyLsStorage::new(dir.path().join(format!("{}.lusf", i)), &options)
+    }))?;
+    Ok(())
+}
+
+#[cfg(feature = "cannyls")]
+fn cannyls_options() -> kvs::CannyLsOptions {
+    kvs::CannyLsOptions {
+        capacity: 64 * 1024 * 1024,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,137 @@
+//! Tests of the exec backend.
+//!
+//! This test has no harness, so that its executable can also be spawned as the child process
+//! of `ExecKvs`: with `EKVSB_TEST_EXEC_SERVE` set, it serves a `HashMap` on stdin and stdout.
+#[macro_use]
+extern crate trackable;
+
+use ekvsb::kvs::{self, testing, KeyValueStore};
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::process::{self, Command};
+use trackable::result::TestResult;
+
+const SERVE_ENV: &str = "EKVSB_TEST_EXEC_SERVE";
+
+fn main() {
+    if env::var_os(SERVE_ENV).is_some() {
+        if let Err(e) = kvs::exec::serve(&mut HashMap::new(), io::stdin(), io::stdout()) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut tests: Vec<(&str, fn() -> TestResult)> = vec![
+        ("exec_conforms", exec_conforms),
+        (
+            "exec_server_skips_unknown_operations",
+            exec_server_skips_unknown_operations,
+        ),
+        (
+            "exec_server_rejects_malformed_requests",
+            exec_server_rejects_malformed_requests,
+        ),
+        (
+            "exec_server_fails_on_truncated_request",
+            exec_server_fails_on_truncated_request,
+        ),
+    ];
+    #[cfg(unix)]
+    tests.push((
+        "exec_fails_after_malformed_response",
+        exec_fails_after_malformed_response,
+    ));
+    #[cfg(unix)]
+    tests.push((
+        "exec_fails_on_truncated_response",
+        exec_fails_on_truncated_response,
+    ));
+
+    let mut failed = 0;
+    for (name, test) in tests {
+        match test() {
+            Ok(()) => println!("test {} ... ok", name),
+            Err(e) => {
+                println!("test {} ... FAILED\n{:?}", name, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn exec_conforms() -> TestResult {
+    let exe = track_any_err!(env::current_exe())?;
+    track!(testing::run_conformance(|| {
+        let mut command = Command::new(&exe);
+        command.env(SERVE_ENV, "1");
+        kvs::ExecKvs::new(command)
+    }))?;
+    Ok(())
+}
+
+fn exec_server_skips_unknown_operations() -> TestResult {
+    // An unknown operation with three bytes of fields, and then a get of "foo".
+    let input = b"\0\0\0\x04X\x01\x02\x03\0\0\0\x08G\0\0\0\x03foo";
+    let mut output = Vec::new();
+    track!(kvs::exec::serve(
+        &mut HashMap::new(),
+        &input[..],
+        &mut output
+    ))?;
+    assert_eq!(output, [2, 0, 0]);
+    Ok(())
+}
+
+fn exec_server_rejects_malformed_requests() -> TestResult {
+    // A get whose key is longer than the request, and then a sync.
+    let input = b"\0\0\0\x08G\0\0\x01\x00foo\0\0\0\x01S";
+    let mut output = Vec::new();
+    track!(kvs::exec::serve(
+        &mut HashMap::new(),
+        &input[..],
+        &mut output
+    ))?;
+    assert_eq!(output[0], 1);
+    assert_eq!(output[output.len() - 1], 0);
+    Ok(())
+}
+
+fn exec_server_fails_on_truncated_request() -> TestResult {
+    // The length claims 4 GiB, which must not be allocated before the bytes arrive.
+    let input = b"\xff\xff\xff\xffG";
+    let mut output = Vec::new();
+    let result = kvs::exec::serve(&mut HashMap::new(), &input[..], &mut output);
+    assert!(result.is_err());
+    assert!(output.is_empty());
+    Ok(())
+}
+
+#[cfg(unix)]
+fn exec_fails_after_malformed_response() -> TestResult {
+    // Replies "ok" with an invalid existence byte, and then never replies.
+    let mut command = Command::new("sh");
+    command.args(&["-c", "printf '\\000\\007'; cat > /dev/null"]);
+    let mut kvs = track!(kvs::ExecKvs::new(command))?;
+    assert!(kvs.put(b"foo", b"bar").is_err());
+    assert!(kvs.put(b"foo", b"bar").is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+fn exec_fails_on_truncated_response() -> TestResult {
+    // Replies a present value of 4 GiB without its bytes, and then closes stdout.
+    let mut command = Command::new("sh");
+    command.args(&[
+        "-c",
+        "printf '\\000\\001\\377\\377\\377\\377'; exec >&-; cat > /dev/null",
+    ]);
+    let mut kvs = track!(kvs::ExecKvs::new(command))?;
+    assert!(kvs.get(b"foo").is_err());
+    assert!(kvs.get(b"foo").is_err());
+    Ok(())
+}